@@ -0,0 +1,200 @@
+use crate::clock;
+use crate::config::{AckConfig, Config, RecipientGroup};
+use crate::database::{AckOutcome, Database, NotificationAck};
+use crate::error::{Result, WeatherCheckerError};
+use crate::notification::{EmailNotifier, Notification};
+use axum::extract::{ConnectInfo, Path, State};
+use axum::http::StatusCode;
+use axum::response::Html;
+use axum::routing::get;
+use axum::{Form, Router};
+use chrono::{Duration, FixedOffset};
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
+
+/// Serve the acknowledgement endpoint until the cancellation token fires
+/// `GET /ack/{token}` shows a confirmation form and `POST /ack/{token}` records the
+/// acknowledgement, so link scanners that prefetch URLs cannot acknowledge by accident.
+pub async fn serve(config: &AckConfig, cancel_token: CancellationToken) -> Result<()> {
+    let db = Database::new().await?;
+    let app = Router::new()
+        .route("/ack/{token}", get(show_form).post(record_ack))
+        .with_state(Arc::new(db));
+
+    let listener = tokio::net::TcpListener::bind(&config.listen_addr).await?;
+    tracing::info!(
+        "Acknowledgement endpoint listening on {}",
+        config.listen_addr
+    );
+
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(async move { cancel_token.cancelled().await })
+    .await?;
+
+    tracing::info!("Acknowledgement endpoint stopped");
+    Ok(())
+}
+
+async fn show_form(
+    State(db): State<Arc<Database>>,
+    Path(token): Path<String>,
+) -> (StatusCode, Html<String>) {
+    match db.get_ack(&token).await {
+        Ok(Some(ack)) => {
+            let status = match (&ack.acked_at, &ack.acked_by) {
+                (Some(at), Some(by)) => format!(
+                    "<p>確認済み: {} ({})</p>",
+                    escape(by),
+                    at.with_timezone(&jst()).format("%Y/%m/%d %H:%M:%S")
+                ),
+                _ => String::new(),
+            };
+            let page = format!(
+                "<html><body><h1>{}</h1>{}<form method=\"post\">\
+                 <label>確認者 <input name=\"by\" required></label> \
+                 <button type=\"submit\">確認しました</button></form></body></html>",
+                escape(&ack.summary),
+                status
+            );
+            (StatusCode::OK, Html(page))
+        }
+        Ok(None) => (StatusCode::NOT_FOUND, Html("Unknown token".to_string())),
+        Err(e) => {
+            tracing::error!("Failed to load acknowledgement {}: {}", token, e);
+            (StatusCode::INTERNAL_SERVER_ERROR, Html(String::new()))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct AckForm {
+    by: String,
+}
+
+async fn record_ack(
+    State(db): State<Arc<Database>>,
+    Path(token): Path<String>,
+    ConnectInfo(remote): ConnectInfo<SocketAddr>,
+    Form(form): Form<AckForm>,
+) -> (StatusCode, Html<String>) {
+    let by = form.by.trim();
+    if by.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Html("確認者を入力してください".to_string()),
+        );
+    }
+
+    match db
+        .acknowledge(&token, by, &format!("http {}", remote))
+        .await
+    {
+        Ok(AckOutcome::Recorded(ack)) => {
+            tracing::info!("Notification acknowledged by {}: {}", by, ack.summary);
+            (
+                StatusCode::OK,
+                Html(format!(
+                    "<p>確認を記録しました: {}</p>",
+                    escape(&ack.summary)
+                )),
+            )
+        }
+        Ok(AckOutcome::AlreadyAcknowledged(ack)) => (
+            StatusCode::OK,
+            Html(format!(
+                "<p>既に {} が確認済みです: {}</p>",
+                escape(ack.acked_by.as_deref().unwrap_or("-")),
+                escape(&ack.summary)
+            )),
+        ),
+        Ok(AckOutcome::UnknownToken) => (StatusCode::NOT_FOUND, Html("Unknown token".to_string())),
+        Err(e) => {
+            tracing::error!("Failed to record acknowledgement {}: {}", token, e);
+            (StatusCode::INTERNAL_SERVER_ERROR, Html(String::new()))
+        }
+    }
+}
+
+/// Escalate 警報-level notifications nobody acknowledged in time
+/// Each notification is escalated once; the escalation email carries the same ACK link.
+pub async fn run_escalations() -> Result<()> {
+    let config = Config::from_env()?;
+    let Some(ack_config) = config.ack.clone() else {
+        return Ok(());
+    };
+
+    let db = Database::new().await?;
+    let cutoff = clock::now() - Duration::minutes(ack_config.escalate_after_minutes);
+    let overdue = db.get_ack_overdue(cutoff).await?;
+    if overdue.is_empty() {
+        return Ok(());
+    }
+
+    let group = RecipientGroup {
+        name: "escalation".to_string(),
+        to: ack_config.escalation_to.clone(),
+        bcc: Vec::new(),
+        quiet_hours: None,
+        emergency_only: false,
+    };
+    let notifier = EmailNotifier::new(config);
+
+    for ack in overdue {
+        if let Err(e) = escalate(&db, &notifier, &ack_config, &group, &ack).await {
+            tracing::error!("Escalation of {} failed, will retry: {}", ack.summary, e);
+        }
+    }
+
+    Ok(())
+}
+
+async fn escalate(
+    db: &Database,
+    notifier: &EmailNotifier,
+    ack_config: &AckConfig,
+    group: &RecipientGroup,
+    ack: &NotificationAck,
+) -> Result<()> {
+    let message = db.get_outbox_message(ack.outbox_id).await?.ok_or_else(|| {
+        WeatherCheckerError::Other(format!("Outbox message {} not found", ack.outbox_id))
+    })?;
+    let notification = match Notification::decode(&message.message_type, &message.payload) {
+        Some(Ok(notification)) => notification,
+        _ => {
+            return Err(WeatherCheckerError::Other(format!(
+                "Cannot decode outbox message {}",
+                ack.outbox_id
+            )))
+        }
+    };
+
+    notifier
+        .send_escalation(
+            &notification,
+            group,
+            &ack_config.ack_url(&ack.token),
+            ack_config.escalate_after_minutes,
+        )
+        .await?;
+    db.mark_ack_escalated(ack, &group.to.join(",")).await?;
+    tracing::warn!("Escalated unacknowledged notification: {}", ack.summary);
+    Ok(())
+}
+
+fn jst() -> FixedOffset {
+    FixedOffset::east_opt(9 * 3600).unwrap()
+}
+
+/// Minimal HTML escaping for values shown on the acknowledgement pages
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
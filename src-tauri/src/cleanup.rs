@@ -1,3 +1,4 @@
+use crate::clock;
 use crate::config::Config;
 use crate::database::Database;
 use crate::error::Result;
@@ -39,7 +40,7 @@ impl Cleanup {
             return Ok(());
         }
 
-        let cutoff_date = Utc::now() - Duration::days(30);
+        let cutoff_date = clock::now() - Duration::days(30);
         let mut deleted_count = 0;
 
         for entry in fs::read_dir(&deleted_dir)? {
@@ -0,0 +1,62 @@
+use chrono::{DateTime, Utc};
+use std::sync::{Arc, Mutex, RwLock};
+
+/// Source of the current time
+/// Everything time-dependent (timestamps stored in the database, retention cutoffs,
+/// reminder intervals, notification dates, the heartbeat) reads the time through
+/// `clock::now()`, so tooling can run recorded data through the checker as if it were live.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// Wall-clock time; the default
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// Clock that only moves when told to, for replaying historical data
+pub struct ManualClock {
+    now: Mutex<DateTime<Utc>>,
+}
+
+impl ManualClock {
+    pub fn new(start: DateTime<Utc>) -> Self {
+        Self {
+            now: Mutex::new(start),
+        }
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.now.lock().unwrap() = now;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().unwrap()
+    }
+}
+
+/// Installed clock; `None` means `SystemClock`
+static CLOCK: RwLock<Option<Arc<dyn Clock>>> = RwLock::new(None);
+
+/// Current time according to the installed clock
+pub fn now() -> DateTime<Utc> {
+    match CLOCK.read().unwrap().as_ref() {
+        Some(clock) => clock.now(),
+        None => SystemClock.now(),
+    }
+}
+
+/// Install the clock used by `now()` for the rest of the process
+pub fn set_clock(clock: Arc<dyn Clock>) {
+    *CLOCK.write().unwrap() = Some(clock);
+}
+
+/// Serialises tests that install a clock, since the installed clock is process-wide
+#[cfg(test)]
+pub static TEST_CLOCK_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
@@ -1,5 +1,7 @@
 use crate::error::{Result, WeatherCheckerError};
-use serde::Deserialize;
+use crate::severity::{self, Severity};
+use chrono::{DateTime, FixedOffset, NaiveTime, Utc};
+use serde::{Deserialize, Deserializer};
 use std::env;
 use std::path::Path;
 
@@ -10,6 +12,96 @@ pub struct CityConfig {
     pub name: String,
     /// JMA warning page URL for this city (optional, falls back to default)
     pub url: Option<String>,
+    /// JMA class20s area code (e.g., "2222000"); taken from `url` when omitted
+    pub area_code: Option<String>,
+    /// Notification rules for this city; unset fields fall back to the region's rules
+    #[serde(default)]
+    pub notify: NotificationRules,
+}
+
+/// Which warning changes send a notification
+/// Every field is optional; an empty rule set notifies everything. State is recorded
+/// for every change regardless of these rules.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct NotificationRules {
+    /// Only these kinds notify (e.g. ["大雨警報", "洪水警報"])
+    pub include_kinds: Option<Vec<String>>,
+    /// These kinds never notify
+    pub exclude_kinds: Option<Vec<String>>,
+    /// Lowest severity that notifies: 注意報, 警報 or 特別警報
+    pub min_severity: Option<Severity>,
+    /// Statuses that notify (e.g. ["発表", "解除"] to skip 継続)
+    pub statuses: Option<Vec<String>>,
+}
+
+impl NotificationRules {
+    /// Combine with defaults: each field set here wins, unset fields come from `defaults`
+    pub fn or(&self, defaults: &NotificationRules) -> NotificationRules {
+        NotificationRules {
+            include_kinds: self
+                .include_kinds
+                .clone()
+                .or_else(|| defaults.include_kinds.clone()),
+            exclude_kinds: self
+                .exclude_kinds
+                .clone()
+                .or_else(|| defaults.exclude_kinds.clone()),
+            min_severity: self.min_severity.or(defaults.min_severity),
+            statuses: self.statuses.clone().or_else(|| defaults.statuses.clone()),
+        }
+    }
+
+    /// Whether a change of `warning_kind` to `status` should be notified
+    pub fn allows(&self, warning_kind: &str, status: &str) -> bool {
+        self.allows_kind(warning_kind) && listed(&self.statuses, status) != Some(false)
+    }
+
+    /// Whether `warning_kind` passes the kind and severity rules, whatever its status
+    /// Kinds without a recognised severity suffix pass the severity check.
+    pub fn allows_kind(&self, warning_kind: &str) -> bool {
+        if listed(&self.include_kinds, warning_kind) == Some(false)
+            || listed(&self.exclude_kinds, warning_kind) == Some(true)
+        {
+            return false;
+        }
+
+        match (self.min_severity, severity::classify(warning_kind)) {
+            (Some(min), Some((_, severity))) => severity >= min,
+            _ => true,
+        }
+    }
+}
+
+impl CityConfig {
+    /// Get the class20s area code, either explicit or parsed from the `area_code=` URL parameter
+    pub fn area_code(&self) -> Option<&str> {
+        if let Some(code) = self.area_code.as_deref() {
+            return Some(code);
+        }
+        let url = self.url.as_deref()?;
+        let start = url.find("area_code=")? + "area_code=".len();
+        let code = &url[start..];
+        let end = code
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(code.len());
+        (end > 0).then(|| &code[..end])
+    }
+}
+
+/// Where warning data for a region comes from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceKind {
+    /// VPWW54 XML via the extra.xml Atom feed
+    #[default]
+    Xml,
+    /// bosai warning JSON (`bosai/warning/data/warning/<office>.json`)
+    Json,
+}
+
+/// Whether `value` is in an optional list (`None` when the list is not set)
+fn listed(list: &Option<Vec<String>>, value: &str) -> Option<bool> {
+    list.as_ref().map(|l| l.iter().any(|v| v == value))
 }
 
 /// Monitored region configuration
@@ -19,6 +111,19 @@ pub struct MonitoredRegion {
     pub lmo: String,
     /// List of cities to monitor
     pub cities: Vec<CityConfig>,
+    /// JMA office code for the bosai JSON source (e.g., "220000")
+    pub office_code: Option<String>,
+    /// Primary warning data source
+    #[serde(default)]
+    pub source: SourceKind,
+    /// Source to use when the primary one fails
+    pub fallback_source: Option<SourceKind>,
+    /// Also fetch the other source and log any disagreement
+    #[serde(default)]
+    pub cross_check: bool,
+    /// Default notification rules for the region's cities
+    #[serde(default)]
+    pub notify: NotificationRules,
 }
 
 impl MonitoredRegion {
@@ -34,6 +139,76 @@ impl MonitoredRegion {
             .find(|c| c.name == city)
             .and_then(|c| c.url.as_deref())
     }
+
+    /// Get the effective notification rules for a city (city rules over region defaults)
+    pub fn notification_rules(&self, city: &str) -> NotificationRules {
+        match self.cities.iter().find(|c| c.name == city) {
+            Some(c) => c.notify.or(&self.notify),
+            None => self.notify.clone(),
+        }
+    }
+
+    /// Whether any configured source for this region is the bosai JSON
+    pub fn uses_json(&self) -> bool {
+        self.source == SourceKind::Json
+            || self.fallback_source == Some(SourceKind::Json)
+            || self.cross_check
+    }
+}
+
+/// A set of warning notification recipients sharing delivery preferences
+#[derive(Debug, Clone, Deserialize)]
+pub struct RecipientGroup {
+    /// Group name used in logs and delivery records
+    pub name: String,
+    /// Recipient addresses
+    pub to: Vec<String>,
+    /// Blind-copy addresses
+    #[serde(default)]
+    pub bcc: Vec<String>,
+    /// Window during which low-severity notifications are held for a digest
+    pub quiet_hours: Option<QuietHours>,
+    /// Receive only 特別警報 notifications (the extra recipients of the emergency path)
+    #[serde(default)]
+    pub emergency_only: bool,
+}
+
+/// Daily quiet-hours window in JST; may wrap past midnight (e.g. 22:00-07:00)
+#[derive(Debug, Clone, Deserialize)]
+pub struct QuietHours {
+    /// Window start ("HH:MM", JST)
+    #[serde(deserialize_with = "deserialize_hhmm")]
+    pub start: NaiveTime,
+    /// Window end ("HH:MM", JST); the digest is sent once this is reached
+    #[serde(deserialize_with = "deserialize_hhmm")]
+    pub end: NaiveTime,
+    /// Notifications below this severity are held (at most 警報, so 警報 and above always go out)
+    #[serde(default = "default_hold_below")]
+    pub hold_below: Severity,
+}
+
+fn default_hold_below() -> Severity {
+    Severity::Warning
+}
+
+fn deserialize_hhmm<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<NaiveTime, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    NaiveTime::parse_from_str(&value, "%H:%M").map_err(serde::de::Error::custom)
+}
+
+impl QuietHours {
+    /// Whether the given instant falls inside the window
+    pub fn contains(&self, at: DateTime<Utc>) -> bool {
+        let jst = FixedOffset::east_opt(9 * 3600).unwrap();
+        let time = at.with_timezone(&jst).time();
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
 }
 
 /// Monitor configuration loaded from YAML file
@@ -41,6 +216,9 @@ impl MonitoredRegion {
 pub struct MonitorConfig {
     /// List of monitored regions
     pub monitored_regions: Vec<MonitoredRegion>,
+    /// Warning notification recipients; `EMAIL_TO`/`EMAIL_BCC` are used when empty
+    #[serde(default)]
+    pub recipient_groups: Vec<RecipientGroup>,
 }
 
 impl MonitorConfig {
@@ -69,6 +247,43 @@ impl MonitorConfig {
             ));
         }
 
+        for region in &config.monitored_regions {
+            if region.uses_json() && region.office_code.is_none() {
+                return Err(WeatherCheckerError::Config(format!(
+                    "office_code is required for {} when using the json source or cross_check",
+                    region.lmo
+                )));
+            }
+        }
+
+        for (index, group) in config.recipient_groups.iter().enumerate() {
+            if group.to.is_empty() {
+                return Err(WeatherCheckerError::Config(format!(
+                    "Recipient group '{}' has no recipients",
+                    group.name
+                )));
+            }
+            if config.recipient_groups[..index]
+                .iter()
+                .any(|g| g.name == group.name)
+            {
+                return Err(WeatherCheckerError::Config(format!(
+                    "Duplicate recipient group name '{}'",
+                    group.name
+                )));
+            }
+            if group
+                .quiet_hours
+                .as_ref()
+                .is_some_and(|q| q.hold_below > Severity::Warning)
+            {
+                return Err(WeatherCheckerError::Config(format!(
+                    "quiet_hours.hold_below for '{}' must be 注意報 or 警報; 警報 and above are never held",
+                    group.name
+                )));
+            }
+        }
+
         tracing::info!(
             "Loaded {} monitored regions from config",
             config.monitored_regions.len()
@@ -81,6 +296,24 @@ impl MonitorConfig {
         Ok(config)
     }
 
+    /// Recipient groups for warning notifications
+    /// Unless a configured group receives every notification, a "default" group built from
+    /// `EMAIL_TO`/`EMAIL_BCC` comes first, so emergency-only groups can be added on their own.
+    pub fn recipient_groups(&self, config: &Config) -> Vec<RecipientGroup> {
+        let mut groups = Vec::with_capacity(self.recipient_groups.len() + 1);
+        if self.recipient_groups.iter().all(|g| g.emergency_only) {
+            groups.push(RecipientGroup {
+                name: "default".to_string(),
+                to: vec![config.email_to.clone()],
+                bcc: config.email_bcc.iter().cloned().collect(),
+                quiet_hours: None,
+                emergency_only: false,
+            });
+        }
+        groups.extend(self.recipient_groups.iter().cloned());
+        groups
+    }
+
     /// Get default config file path
     pub fn default_path() -> String {
         env::var("CONFIG_PATH").unwrap_or_else(|_| "config.yaml".to_string())
@@ -96,14 +329,234 @@ pub struct Config {
     pub gmail_from: String,
     pub email_to: String,
     pub email_bcc: Option<String>,
+    /// Timeout of each SMTP connect, command and response, in seconds
+    pub smtp_timeout_secs: u64,
+    /// TCP connect timeout for JMA requests, in seconds
+    pub http_connect_timeout_secs: u64,
+    /// Overall request timeout for JMA requests, in seconds
+    pub http_timeout_secs: u64,
+    /// Number of retries after the first attempt on transient HTTP errors
+    pub http_max_retries: u32,
+    /// Base delay for exponential backoff between retries, in milliseconds
+    pub http_retry_base_ms: u64,
+    /// User-Agent header sent to the JMA server
+    pub http_user_agent: String,
+    /// Outbound proxy for JMA requests (http://, https:// or socks5:// URL)
+    pub proxy_url: Option<String>,
+    /// Comma-separated hosts/domains/CIDRs that bypass the proxy
+    pub no_proxy: Option<String>,
+    /// PEM files with additional trusted CA certificates (HTTP client and SMTP)
+    pub extra_ca_certs: Vec<String>,
+    /// WebSub push subscription settings (None when push delivery is disabled)
+    pub websub: Option<WebSubConfig>,
+    /// Acknowledgement workflow settings (None when disabled)
+    pub ack: Option<AckConfig>,
+    /// Maximum number of regions checked concurrently in one cycle
+    pub check_concurrency: usize,
+    /// Reminder interval for active 注意報, in hours (0 = no reminders)
+    pub reminder_advisory_hours: u64,
+    /// Reminder interval for active 警報, in hours (0 = no reminders)
+    pub reminder_warning_hours: u64,
+    /// Reminder interval for active 特別警報, in hours (0 = no reminders)
+    pub reminder_emergency_hours: u64,
+    /// Repeat interval for a 特別警報 until it is acknowledged, in minutes (0 = off)
+    pub emergency_reminder_minutes: u64,
+    /// Alert when extra.xml's `<updated>` is older than this many minutes (0 = off)
+    pub feed_stale_minutes: u64,
+    /// Notify about a configured city missing from this many consecutive reports (0 = off)
+    pub city_missing_reports: u64,
+    /// Consecutive failed checks before a "checks failing" alert
+    pub failure_alert_threshold: u32,
+    /// Secondary SMTP server for alerts (None = alerts use the primary Gmail account)
+    pub alert_smtp: Option<AlertSmtpConfig>,
+    /// Alert recipients; defaults to `EMAIL_TO`
+    pub alert_to: Vec<String>,
+    /// Dry-run mode: emails are logged instead of sent, and the XML cache and database
+    /// are scratch copies under `DRY_RUN_DIR`
+    pub dry_run: bool,
+    /// Live database the scratch database is seeded from at startup (dry-run only)
+    pub seed_db_path: Option<String>,
+}
+
+/// WebSub (PubSubHubbub) subscriber configuration
+#[derive(Debug, Clone)]
+pub struct WebSubConfig {
+    /// Local address the subscriber endpoint listens on (e.g. "0.0.0.0:8080")
+    pub listen_addr: String,
+    /// Public URL of the subscriber endpoint registered with the hub
+    pub callback_url: String,
+    /// Hub URL to subscribe through
+    pub hub_url: String,
+    /// Topic (feed) URL to subscribe to
+    pub topic_url: String,
+    /// Shared secret for HMAC signatures on content delivery
+    pub secret: String,
+    /// Requested subscription lease; renewed before it expires
+    pub lease_secs: u64,
+    /// Fall back to polling when no push has arrived for this long
+    pub fallback_secs: u64,
+}
+
+impl WebSubConfig {
+    fn from_env() -> Result<Option<Self>> {
+        if !parse_env("WEBSUB_ENABLED", false)? {
+            return Ok(None);
+        }
+
+        let required = |name: &str| {
+            env::var(name).map_err(|_| {
+                WeatherCheckerError::Config(format!(
+                    "{} not set (required when WEBSUB_ENABLED)",
+                    name
+                ))
+            })
+        };
+
+        Ok(Some(Self {
+            listen_addr: env::var("WEBSUB_LISTEN_ADDR")
+                .unwrap_or_else(|_| "0.0.0.0:8080".to_string()),
+            callback_url: required("WEBSUB_CALLBACK_URL")?,
+            hub_url: required("WEBSUB_HUB_URL")?,
+            topic_url: env::var("WEBSUB_TOPIC_URL")
+                .unwrap_or_else(|_| crate::jma_feed::EXTRA_XML_URL.to_string()),
+            secret: required("WEBSUB_SECRET")?,
+            lease_secs: parse_env("WEBSUB_LEASE_SECS", 86400)?,
+            fallback_secs: parse_env("WEBSUB_FALLBACK_SECS", 1800)?,
+        }))
+    }
+}
+
+/// Acknowledgement workflow for 警報-level notifications
+#[derive(Debug, Clone)]
+pub struct AckConfig {
+    /// Local address the acknowledgement endpoint listens on
+    pub listen_addr: String,
+    /// Base URL of the endpoint as reachable by recipients (used in ACK links)
+    pub base_url: String,
+    /// Escalate when a notification is not acknowledged within this many minutes
+    pub escalate_after_minutes: i64,
+    /// Secondary recipients for escalations
+    pub escalation_to: Vec<String>,
+}
+
+impl AckConfig {
+    fn from_env() -> Result<Option<Self>> {
+        if !parse_env("ACK_ENABLED", false)? {
+            return Ok(None);
+        }
+
+        let listen_addr =
+            env::var("ACK_LISTEN_ADDR").unwrap_or_else(|_| "127.0.0.1:8081".to_string());
+        let escalation_to = env_list("ACK_ESCALATION_TO");
+        if escalation_to.is_empty() {
+            return Err(WeatherCheckerError::Config(
+                "ACK_ESCALATION_TO not set (required when ACK_ENABLED)".into(),
+            ));
+        }
+
+        Ok(Some(Self {
+            base_url: env::var("ACK_BASE_URL")
+                .unwrap_or_else(|_| format!("http://{}", listen_addr))
+                .trim_end_matches('/')
+                .to_string(),
+            listen_addr,
+            escalate_after_minutes: parse_env("ACK_ESCALATE_MINUTES", 30)?,
+            escalation_to,
+        }))
+    }
+
+    /// Acknowledgement URL for a token
+    pub fn ack_url(&self, token: &str) -> String {
+        format!("{}/ack/{}", self.base_url, token)
+    }
+}
+
+/// TLS mode of the alert SMTP server
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmtpTls {
+    /// STARTTLS upgrade (typically port 587)
+    StartTls,
+    /// Implicit TLS (typically port 465)
+    Wrapper,
+    /// Plain connection, e.g. a local MTA on port 25
+    None,
+}
+
+/// Secondary SMTP server for operational alerts, independent of the Gmail account
+#[derive(Debug, Clone)]
+pub struct AlertSmtpConfig {
+    pub host: String,
+    pub port: u16,
+    pub tls: SmtpTls,
+    /// Credentials; both unset for servers that accept unauthenticated relay
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub from: String,
+}
+
+impl AlertSmtpConfig {
+    fn from_env() -> Result<Option<Self>> {
+        let Ok(host) = env::var("ALERT_SMTP_HOST") else {
+            return Ok(None);
+        };
+
+        let tls = match env::var("ALERT_SMTP_TLS").as_deref() {
+            Err(_) | Ok("starttls") => SmtpTls::StartTls,
+            Ok("tls") => SmtpTls::Wrapper,
+            Ok("none") => SmtpTls::None,
+            Ok(other) => {
+                return Err(WeatherCheckerError::Config(format!(
+                    "Invalid value for ALERT_SMTP_TLS: '{}' (expected starttls, tls or none)",
+                    other
+                )))
+            }
+        };
+        let default_port = match tls {
+            SmtpTls::StartTls => 587,
+            SmtpTls::Wrapper => 465,
+            SmtpTls::None => 25,
+        };
+
+        Ok(Some(Self {
+            port: parse_env("ALERT_SMTP_PORT", default_port)?,
+            tls,
+            username: env::var("ALERT_SMTP_USER").ok(),
+            password: env::var("ALERT_SMTP_PASS").ok(),
+            from: env::var("ALERT_FROM").map_err(|_| {
+                WeatherCheckerError::Config(
+                    "ALERT_FROM not set (required with ALERT_SMTP_HOST)".into(),
+                )
+            })?,
+            host,
+        }))
+    }
 }
 
 impl Config {
     pub fn from_env() -> Result<Self> {
-        Ok(Self {
-            data_dir: env::var("DATADIR").unwrap_or_else(|_| "data/xml".to_string()),
-            deleted_dir: env::var("DELETED_DIR").unwrap_or_else(|_| "data/deleted".to_string()),
-            db_path: env::var("DB_PATH").unwrap_or_else(|_| "data/weather.sqlite3".to_string()),
+        let data_dir = env::var("DATADIR").unwrap_or_else(|_| "data/xml".to_string());
+        let deleted_dir = env::var("DELETED_DIR").unwrap_or_else(|_| "data/deleted".to_string());
+        let db_path = env::var("DB_PATH").unwrap_or_else(|_| "data/weather.sqlite3".to_string());
+
+        // Dry-run state lives in its own directory so the live cache, database and
+        // moved XML files are never touched
+        let dry_run = parse_env("DRY_RUN", false)?;
+        let (data_dir, deleted_dir, db_path, seed_db_path) = if dry_run {
+            let dir = env::var("DRY_RUN_DIR").unwrap_or_else(|_| "data/dry-run".to_string());
+            (
+                format!("{}/xml", dir),
+                format!("{}/deleted", dir),
+                format!("{}/weather.sqlite3", dir),
+                Some(db_path),
+            )
+        } else {
+            (data_dir, deleted_dir, db_path, None)
+        };
+
+        let config = Self {
+            data_dir,
+            deleted_dir,
+            db_path,
             gmail_app_pass: env::var("GMAIL_APP_PASS")
                 .map_err(|_| WeatherCheckerError::Config("GMAIL_APP_PASS not set".into()))?,
             gmail_from: env::var("GMAIL_FROM")
@@ -111,6 +564,279 @@ impl Config {
             email_to: env::var("EMAIL_TO")
                 .map_err(|_| WeatherCheckerError::Config("EMAIL_TO not set".into()))?,
             email_bcc: env::var("EMAIL_BCC").ok(),
+            smtp_timeout_secs: parse_env("SMTP_TIMEOUT_SECS", 10)?,
+            http_connect_timeout_secs: parse_env("HTTP_CONNECT_TIMEOUT_SECS", 10)?,
+            http_timeout_secs: parse_env("HTTP_TIMEOUT_SECS", 30)?,
+            http_max_retries: parse_env("HTTP_MAX_RETRIES", 3)?,
+            http_retry_base_ms: parse_env("HTTP_RETRY_BASE_MS", 500)?,
+            http_user_agent: env::var("HTTP_USER_AGENT").unwrap_or_else(|_| {
+                format!(
+                    "weather-warning-checker/{} (+https://github.com/aktnk/weather-warning-checker)",
+                    env!("CARGO_PKG_VERSION")
+                )
+            }),
+            proxy_url: env::var("PROXY_URL").ok().filter(|v| !v.trim().is_empty()),
+            no_proxy: env::var("NO_PROXY").ok().filter(|v| !v.trim().is_empty()),
+            extra_ca_certs: env_list("EXTRA_CA_CERTS"),
+            // Neither endpoint runs in dry-run mode: both would compete with a live instance
+            websub: if dry_run {
+                None
+            } else {
+                WebSubConfig::from_env()?
+            },
+            ack: if dry_run { None } else { AckConfig::from_env()? },
+            check_concurrency: parse_env("CHECK_CONCURRENCY", 4)?,
+            reminder_advisory_hours: parse_env("REMINDER_ADVISORY_HOURS", 0)?,
+            reminder_warning_hours: parse_env("REMINDER_WARNING_HOURS", 6)?,
+            reminder_emergency_hours: parse_env("REMINDER_EMERGENCY_HOURS", 3)?,
+            emergency_reminder_minutes: parse_env("EMERGENCY_REMINDER_MINUTES", 30)?,
+            feed_stale_minutes: parse_env("FEED_STALE_MINUTES", 60)?,
+            city_missing_reports: parse_env("CITY_MISSING_REPORTS", 3)?,
+            failure_alert_threshold: parse_env("FAILURE_ALERT_THRESHOLD", 3)?.max(1),
+            alert_smtp: AlertSmtpConfig::from_env()?,
+            alert_to: env_list("ALERT_TO"),
+            dry_run,
+            seed_db_path,
+        };
+        config.check_smtp_proxy()?;
+        Ok(config)
+    }
+
+    /// Refuse a proxy configuration that SMTP cannot follow
+    /// lettre connects directly and cannot tunnel through `PROXY_URL`, so on a proxy-only
+    /// host mail would silently fail. Listing the SMTP hosts in `NO_PROXY` confirms they
+    /// are reachable directly.
+    fn check_smtp_proxy(&self) -> Result<()> {
+        if self.proxy_url.is_none() {
+            return Ok(());
+        }
+
+        let mut hosts = vec![crate::notification::SMTP_RELAY];
+        if let Some(alert_smtp) = &self.alert_smtp {
+            hosts.push(&alert_smtp.host);
+        }
+        let direct: Vec<&str> = hosts
+            .into_iter()
+            .filter(|host| !self.bypasses_proxy(host))
+            .collect();
+        if direct.is_empty() {
+            return Ok(());
+        }
+
+        Err(WeatherCheckerError::Config(format!(
+            "PROXY_URL is set, but SMTP cannot use a proxy and connects to {} directly; \
+             add {} to NO_PROXY once direct SMTP access is confirmed",
+            direct.join(", "),
+            if direct.len() == 1 { "it" } else { "them" }
+        )))
+    }
+
+    /// Whether `NO_PROXY` covers a host (exact name, domain suffix or `*`)
+    fn bypasses_proxy(&self, host: &str) -> bool {
+        let Some(no_proxy) = &self.no_proxy else {
+            return false;
+        };
+        no_proxy.split(',').map(str::trim).any(|entry| {
+            let domain = entry.trim_start_matches('.');
+            entry == "*"
+                || (!domain.is_empty()
+                    && (host == domain || host.ends_with(&format!(".{}", domain))))
+        })
+    }
+
+    /// Reminder interval for a severity, or `None` when reminders are off for it
+    pub fn reminder_interval(&self, severity: Severity) -> Option<chrono::Duration> {
+        let hours = match severity {
+            Severity::Advisory => self.reminder_advisory_hours,
+            Severity::Warning => self.reminder_warning_hours,
+            Severity::Emergency => self.reminder_emergency_hours,
+        };
+        (hours > 0).then(|| chrono::Duration::hours(hours as i64))
+    }
+
+    /// Repeat interval for a 特別警報 nobody has acknowledged yet, or `None` when off
+    pub fn emergency_reminder_interval(&self) -> Option<chrono::Duration> {
+        let minutes = self.emergency_reminder_minutes;
+        (minutes > 0).then(|| chrono::Duration::minutes(minutes as i64))
+    }
+
+    /// Read the PEM contents of every configured extra CA certificate file
+    pub fn load_extra_ca_pems(&self) -> Result<Vec<Vec<u8>>> {
+        self.extra_ca_certs
+            .iter()
+            .map(|path| {
+                std::fs::read(path).map_err(|e| {
+                    WeatherCheckerError::Config(format!(
+                        "Failed to read CA certificate '{}': {}",
+                        path, e
+                    ))
+                })
+            })
+            .collect()
+    }
+}
+
+/// Parse an optional comma-separated environment variable into its non-empty items
+fn env_list(name: &str) -> Vec<String> {
+    env::var(name)
+        .map(|v| {
+            v.split(',')
+                .map(str::trim)
+                .filter(|a| !a.is_empty())
+                .map(String::from)
+                .collect()
         })
+        .unwrap_or_default()
+}
+
+/// Parse an optional numeric environment variable, falling back to a default when unset
+fn parse_env<T: std::str::FromStr>(name: &str, default: T) -> Result<T> {
+    match env::var(name) {
+        Ok(value) => value.trim().parse().map_err(|_| {
+            WeatherCheckerError::Config(format!("Invalid value for {}: '{}'", name, value))
+        }),
+        Err(_) => Ok(default),
+    }
+}
+
+/// Configuration from the environment with only the required mail settings set
+/// The variables are set once per test process, before any test reads them.
+#[cfg(test)]
+pub fn test_config() -> Config {
+    static SET_ENV: std::sync::Once = std::sync::Once::new();
+    SET_ENV.call_once(|| {
+        env::set_var("GMAIL_APP_PASS", "x");
+        env::set_var("GMAIL_FROM", "from@example.com");
+        env::set_var("EMAIL_TO", "to@example.com");
+    });
+    Config::from_env().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(names: &[&str]) -> Option<Vec<String>> {
+        Some(names.iter().map(|s| s.to_string()).collect())
+    }
+
+    #[test]
+    fn notification_rules_filter_kinds_severity_and_status() {
+        let everything = NotificationRules::default();
+        let include = NotificationRules {
+            include_kinds: kinds(&["大雨警報", "洪水警報"]),
+            ..Default::default()
+        };
+        let exclude = NotificationRules {
+            exclude_kinds: kinds(&["乾燥注意報"]),
+            ..Default::default()
+        };
+        let warnings_up = NotificationRules {
+            min_severity: Some(Severity::Warning),
+            ..Default::default()
+        };
+        let issued_and_cancelled = NotificationRules {
+            statuses: kinds(&["発表", "解除"]),
+            ..Default::default()
+        };
+
+        let cases = [
+            (&everything, "乾燥注意報", "継続", true),
+            (&include, "大雨警報", "発表", true),
+            (&include, "大雨注意報", "発表", false),
+            (&exclude, "乾燥注意報", "発表", false),
+            (&exclude, "大雨注意報", "発表", true),
+            (&warnings_up, "大雨注意報", "発表", false),
+            (&warnings_up, "大雨警報", "発表", true),
+            (&warnings_up, "大雨特別警報", "発表", true),
+            (&warnings_up, "強風注意報", "発表", false),
+            (&warnings_up, "暴風警報", "発表", true),
+            // Kinds without a severity suffix pass the severity check
+            (&warnings_up, "その他", "発表", true),
+            (&issued_and_cancelled, "大雨警報", "発表", true),
+            (&issued_and_cancelled, "大雨警報", "解除", true),
+            (&issued_and_cancelled, "大雨警報", "継続", false),
+        ];
+        for (rules, kind, status, expected) in cases {
+            assert_eq!(
+                rules.allows(kind, status),
+                expected,
+                "{:?}: {} {}",
+                rules,
+                kind,
+                status
+            );
+        }
+    }
+
+    #[test]
+    fn allows_kind_ignores_status_filter() {
+        let rules = NotificationRules {
+            min_severity: Some(Severity::Warning),
+            statuses: kinds(&["発表"]),
+            ..Default::default()
+        };
+        assert!(rules.allows_kind("大雨警報"));
+        assert!(!rules.allows("大雨警報", "継続"));
+        assert!(!rules.allows_kind("大雨注意報"));
+    }
+
+    #[test]
+    fn city_rules_fall_back_to_defaults_per_field() {
+        let city = NotificationRules {
+            min_severity: Some(Severity::Emergency),
+            ..Default::default()
+        };
+        let region = NotificationRules {
+            min_severity: Some(Severity::Warning),
+            statuses: kinds(&["発表"]),
+            ..Default::default()
+        };
+        let rules = city.or(&region);
+        assert_eq!(rules.min_severity, Some(Severity::Emergency));
+        assert_eq!(rules.statuses, kinds(&["発表"]));
+        assert!(!rules.allows("大雨警報", "発表"));
+        assert!(rules.allows("大雨特別警報", "発表"));
+    }
+
+    fn quiet_hours(start: &str, end: &str) -> QuietHours {
+        QuietHours {
+            start: NaiveTime::parse_from_str(start, "%H:%M").unwrap(),
+            end: NaiveTime::parse_from_str(end, "%H:%M").unwrap(),
+            hold_below: Severity::Warning,
+        }
+    }
+
+    /// Instant at the given UTC time of day
+    fn utc(time: &str) -> DateTime<Utc> {
+        format!("2025-01-01T{}:00Z", time).parse().unwrap()
+    }
+
+    #[test]
+    fn quiet_hours_compare_in_jst() {
+        let cases = [
+            // 22:00-07:00 JST wraps midnight
+            ("22:00", "07:00", "12:59", false), // 21:59 JST
+            ("22:00", "07:00", "13:00", true),  // 22:00 JST, start inclusive
+            ("22:00", "07:00", "15:00", true),  // 00:00 JST
+            ("22:00", "07:00", "21:59", true),  // 06:59 JST
+            ("22:00", "07:00", "22:00", false), // 07:00 JST, end exclusive
+            // 01:00-05:00 JST stays within one day
+            ("01:00", "05:00", "15:59", false), // 00:59 JST
+            ("01:00", "05:00", "16:00", true),  // 01:00 JST
+            ("01:00", "05:00", "19:59", true),  // 04:59 JST
+            ("01:00", "05:00", "20:00", false), // 05:00 JST
+            ("01:00", "05:00", "01:00", false), // 10:00 JST
+        ];
+        for (start, end, at, expected) in cases {
+            assert_eq!(
+                quiet_hours(start, end).contains(utc(at)),
+                expected,
+                "{}-{} at {}Z",
+                start,
+                end,
+                at
+            );
+        }
     }
 }
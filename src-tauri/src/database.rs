@@ -1,3 +1,4 @@
+use crate::clock;
 use crate::config::Config;
 use crate::error::Result;
 use chrono::{DateTime, Utc};
@@ -7,6 +8,7 @@ use sqlx::{Row, SqlitePool};
 #[allow(dead_code)]
 pub struct CityReport {
     pub id: Option<i64>,
+    /// Report the status was read from: a VPWW54 filename, or a `bosai:` id for JSON
     pub xml_file: String,
     pub lmo: String,
     pub city: String,
@@ -24,6 +26,135 @@ pub struct VPWW54Xml {
     pub lmo: String,
     pub created_at: Option<DateTime<Utc>>,
     pub is_delete: bool,
+    pub checksum: Option<String>,
+}
+
+/// One status transition of a warning kind for a city (append-only history)
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct WarningEvent {
+    pub lmo: String,
+    pub city: String,
+    pub warning_kind: String,
+    pub new_status: String,
+    pub report_datetime: DateTime<Utc>,
+}
+
+/// A continuous period during which a warning kind was in effect for a city
+#[derive(Debug, Clone)]
+pub struct WarningPeriod {
+    pub lmo: String,
+    pub city: String,
+    pub warning_kind: String,
+    pub started_at: DateTime<Utc>,
+    /// None while the warning is still in effect
+    pub ended_at: Option<DateTime<Utc>>,
+}
+
+impl WarningPeriod {
+    /// Length of the period; ongoing periods are measured up to `now`
+    pub fn duration(&self, now: DateTime<Utc>) -> chrono::Duration {
+        self.ended_at.unwrap_or(now) - self.started_at
+    }
+}
+
+/// Statuses after which a warning kind is no longer in effect
+const INACTIVE_STATUSES: [&str; 2] = ["解除", "発表警報・注意報はなし"];
+
+/// Whether a status means the warning kind is in effect
+pub fn is_active_status(status: &str) -> bool {
+    !INACTIVE_STATUSES.contains(&status)
+}
+
+/// `INACTIVE_STATUSES` as an SQL list, e.g. for `status NOT IN (...)`
+fn inactive_statuses_sql() -> String {
+    INACTIVE_STATUSES
+        .iter()
+        .map(|status| format!("'{}'", status))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Prefix of report ids from the bosai JSON source
+/// They are stored in `xml_file` columns but name no downloaded file.
+pub const JSON_REPORT_PREFIX: &str = "bosai:";
+
+/// Whether a report id names a downloaded VPWW54 file
+pub fn is_vpww54_file(report_id: &str) -> bool {
+    !report_id.starts_with(JSON_REPORT_PREFIX)
+}
+
+/// A notification queued in the outbox, delivered by the dispatcher
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct OutboxMessage {
+    pub id: i64,
+    pub dedup_key: String,
+    pub message_type: String,
+    pub payload: String,
+    pub attempts: i64,
+}
+
+/// A notification to enqueue together with a state change
+#[derive(Debug, Clone)]
+pub struct NewOutboxMessage {
+    /// Unique key; enqueueing the same key twice is a no-op
+    pub dedup_key: String,
+    /// Payload type understood by the dispatcher (e.g. "warning")
+    pub message_type: String,
+    /// JSON-encoded payload
+    pub payload: String,
+}
+
+/// One city report state change to commit
+pub struct CityReportChange<'a> {
+    /// Record being updated; `None` creates a new one
+    pub previous: Option<&'a CityReport>,
+    pub report: &'a CityReport,
+    /// Whether the change is announced by the queued notification (false: filtered by rules)
+    pub notified: bool,
+}
+
+/// A warning notification held for a recipient group's quiet-hours digest
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct HeldDelivery {
+    /// notification_delivery row id
+    pub id: i64,
+    pub dedup_key: String,
+    pub message_type: String,
+    pub payload: String,
+}
+
+/// Acknowledgement state of a 警報-level notification
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct NotificationAck {
+    pub id: i64,
+    pub token: String,
+    pub outbox_id: i64,
+    /// Notification subject, shown on the acknowledgement page
+    pub summary: String,
+    pub acked_at: Option<DateTime<Utc>>,
+    pub acked_by: Option<String>,
+}
+
+/// Result of an acknowledgement attempt
+pub enum AckOutcome {
+    Recorded(NotificationAck),
+    AlreadyAcknowledged(NotificationAck),
+    UnknownToken,
+}
+
+/// Whether a configured city shows up in its LMO's reports
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct CityPresence {
+    pub lmo: String,
+    pub city: String,
+    pub last_seen_at: Option<DateTime<Utc>>,
+    pub last_seen_report: Option<String>,
+    /// Last distinct report checked for the city
+    pub last_report: String,
+    /// Consecutive distinct reports the city was missing from
+    pub missed_reports: i64,
+    /// When the missing city was notified; cleared once it appears again
+    pub warned_at: Option<DateTime<Utc>>,
 }
 
 pub struct Database {
@@ -33,20 +164,63 @@ pub struct Database {
 impl Database {
     pub async fn new() -> Result<Self> {
         let config = Config::from_env()?;
+        Self::connect(&config.db_path).await
+    }
 
-        tracing::debug!("Database path: {}", config.db_path);
+    /// Open (creating if needed) the database at `db_path`
+    pub async fn connect(db_path: &str) -> Result<Self> {
+        tracing::debug!("Database path: {}", db_path);
 
         // Create data directory if it doesn't exist
-        let db_parent = std::path::Path::new(&config.db_path).parent().unwrap();
+        let db_parent = std::path::Path::new(db_path).parent().unwrap();
         tracing::debug!("Creating parent directory: {:?}", db_parent);
         std::fs::create_dir_all(db_parent)?;
 
-        let db_url = format!("sqlite://{}?mode=rwc", config.db_path);
+        let db_url = format!("sqlite://{}?mode=rwc", db_path);
         tracing::debug!("Connecting to database: {}", db_url);
         let pool = SqlitePool::connect(&db_url).await?;
         Ok(Self { pool })
     }
 
+    /// In dry-run mode, replace the scratch database with a snapshot of the live one
+    /// `VACUUM INTO` gives a consistent copy even while a live instance is writing.
+    /// Without a live database the scratch database simply starts empty.
+    pub async fn seed_scratch() -> Result<()> {
+        let config = Config::from_env()?;
+        let Some(live_path) = &config.seed_db_path else {
+            return Ok(());
+        };
+
+        Self::remove_files(&config.db_path)?;
+
+        if !std::path::Path::new(live_path).exists() {
+            tracing::info!("Dry run: no live database at {}, starting empty", live_path);
+            return Ok(());
+        }
+
+        std::fs::create_dir_all(std::path::Path::new(&config.db_path).parent().unwrap())?;
+        let live = SqlitePool::connect(&format!("sqlite://{}?mode=ro", live_path)).await?;
+        sqlx::query("VACUUM INTO ?")
+            .bind(&config.db_path)
+            .execute(&live)
+            .await?;
+        live.close().await;
+
+        tracing::info!("Dry run: seeded {} from {}", config.db_path, live_path);
+        Ok(())
+    }
+
+    /// Delete a database file together with its WAL and shared-memory files
+    pub fn remove_files(db_path: &str) -> Result<()> {
+        for suffix in ["", "-wal", "-shm"] {
+            let path = format!("{}{}", db_path, suffix);
+            if std::path::Path::new(&path).exists() {
+                std::fs::remove_file(&path)?;
+            }
+        }
+        Ok(())
+    }
+
     pub async fn init_schema(&self) -> Result<()> {
         // Create tables if they don't exist
         sqlx::query(
@@ -68,13 +242,18 @@ impl Database {
                 xml_file TEXT NOT NULL,
                 lmo TEXT NOT NULL,
                 created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
-                is_delete BOOLEAN DEFAULT 0
+                is_delete BOOLEAN DEFAULT 0,
+                checksum TEXT
             )
             "#,
         )
         .execute(&self.pool)
         .await?;
 
+        // Databases created before the checksum column was introduced
+        self.add_column_if_missing("vpww54xml", "checksum", "TEXT")
+            .await?;
+
         sqlx::query(
             r#"
             CREATE TABLE IF NOT EXISTS city_report (
@@ -92,10 +271,163 @@ impl Database {
         .execute(&self.pool)
         .await?;
 
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS notification_outbox (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                dedup_key TEXT NOT NULL UNIQUE,
+                message_type TEXT NOT NULL,
+                payload TEXT NOT NULL,
+                status TEXT NOT NULL DEFAULT 'pending',
+                attempts INTEGER NOT NULL DEFAULT 0,
+                last_error TEXT,
+                next_attempt_at TIMESTAMP,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                sent_at TIMESTAMP
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS warning_event (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                lmo TEXT NOT NULL,
+                city TEXT NOT NULL,
+                warning_kind TEXT NOT NULL,
+                previous_status TEXT,
+                new_status TEXT NOT NULL,
+                report_datetime TIMESTAMP NOT NULL,
+                xml_file TEXT NOT NULL,
+                -- Outbox dedup key of the notification for this transition, if one was queued
+                notification_key TEXT,
+                -- queued, held (quiet-hours digest), sent, failed, filtered (notification rules) or none
+                notification_status TEXT NOT NULL DEFAULT 'none',
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_warning_event_city ON warning_event (city, warning_kind, report_datetime)",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Acknowledgement tokens for 警報-level notifications, one per outbox message
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS notification_ack (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                token TEXT NOT NULL UNIQUE,
+                outbox_id INTEGER NOT NULL UNIQUE,
+                summary TEXT NOT NULL,
+                created_at TIMESTAMP NOT NULL,
+                acked_at TIMESTAMP,
+                acked_by TEXT,
+                escalated_at TIMESTAMP
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Audit trail of everything that happened to an acknowledgement token
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS ack_audit (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                token TEXT NOT NULL,
+                action TEXT NOT NULL,
+                actor TEXT,
+                detail TEXT,
+                created_at TIMESTAMP NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Per recipient group delivery state of outbox messages ('sent' or 'held' for a digest)
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS notification_delivery (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                outbox_id INTEGER NOT NULL,
+                recipient_group TEXT NOT NULL,
+                status TEXT NOT NULL,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                sent_at TIMESTAMP,
+                UNIQUE(outbox_id, recipient_group)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Last appearance of each configured city in its LMO's reports
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS city_presence (
+                lmo TEXT NOT NULL,
+                city TEXT NOT NULL,
+                last_seen_at TIMESTAMP,
+                last_seen_report TEXT,
+                last_report TEXT NOT NULL,
+                missed_reports INTEGER NOT NULL DEFAULT 0,
+                warned_at TIMESTAMP,
+                PRIMARY KEY (lmo, city)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Newest <updated> seen per Atom feed, for stale-feed detection
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS feed_freshness (
+                feed TEXT PRIMARY KEY,
+                last_updated TIMESTAMP NOT NULL,
+                checked_at TIMESTAMP NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
         tracing::info!("Database schema initialized");
         Ok(())
     }
 
+    /// Add a column to an existing table unless it is already present
+    /// `CREATE TABLE IF NOT EXISTS` does not alter tables created by older versions.
+    async fn add_column_if_missing(&self, table: &str, column: &str, decl: &str) -> Result<()> {
+        let columns = sqlx::query(&format!("PRAGMA table_info({})", table))
+            .fetch_all(&self.pool)
+            .await?;
+
+        let exists = columns
+            .iter()
+            .any(|row| row.get::<String, _>("name") == column);
+
+        if !exists {
+            tracing::info!("Adding column {}.{}", table, column);
+            sqlx::query(&format!(
+                "ALTER TABLE {} ADD COLUMN {} {}",
+                table, column, decl
+            ))
+            .execute(&self.pool)
+            .await?;
+        }
+
+        Ok(())
+    }
+
     // Extra table operations
     pub async fn get_extra_last_modified(&self) -> Result<Option<String>> {
         let row = sqlx::query("SELECT last_modified FROM extra ORDER BY id DESC LIMIT 1")
@@ -106,13 +438,120 @@ impl Database {
     }
 
     pub async fn update_extra(&self, last_modified: &str) -> Result<()> {
-        sqlx::query("INSERT INTO extra (last_modified) VALUES (?)")
+        sqlx::query("INSERT INTO extra (last_modified, created_at) VALUES (?, ?)")
             .bind(last_modified)
+            .bind(clock::now())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Record the feed-level `<updated>` of a fetched or pushed Atom feed
+    pub async fn record_feed_updated(&self, feed: &str, updated: DateTime<Utc>) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO feed_freshness (feed, last_updated, checked_at) VALUES (?, ?, ?) ON CONFLICT(feed) DO UPDATE SET last_updated = excluded.last_updated, checked_at = excluded.checked_at",
+        )
+        .bind(feed)
+        .bind(updated)
+        .bind(clock::now())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Last recorded `<updated>` of a feed
+    pub async fn get_feed_updated(&self, feed: &str) -> Result<Option<DateTime<Utc>>> {
+        let updated = sqlx::query_scalar::<_, DateTime<Utc>>(
+            "SELECT last_updated FROM feed_freshness WHERE feed = ?",
+        )
+        .bind(feed)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(updated)
+    }
+
+    /// Record whether a configured city appears in a report and return its updated presence
+    /// The same report is re-read every cycle until a newer one arrives, so only a report
+    /// differing from `last_report` counts towards `missed_reports`.
+    pub async fn record_city_presence(
+        &self,
+        lmo: &str,
+        city: &str,
+        report: &str,
+        present: bool,
+    ) -> Result<CityPresence> {
+        let existing = sqlx::query_as::<_, CityPresence>(
+            "SELECT * FROM city_presence WHERE lmo = ? AND city = ?",
+        )
+        .bind(lmo)
+        .bind(city)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let presence = match existing {
+            Some(presence) if presence.last_report == report => return Ok(presence),
+            Some(presence) if present => CityPresence {
+                last_seen_at: Some(clock::now()),
+                last_seen_report: Some(report.to_string()),
+                last_report: report.to_string(),
+                missed_reports: 0,
+                warned_at: None,
+                ..presence
+            },
+            Some(presence) => CityPresence {
+                last_report: report.to_string(),
+                missed_reports: presence.missed_reports + 1,
+                ..presence
+            },
+            None => CityPresence {
+                lmo: lmo.to_string(),
+                city: city.to_string(),
+                last_seen_at: present.then(clock::now),
+                last_seen_report: present.then(|| report.to_string()),
+                last_report: report.to_string(),
+                missed_reports: if present { 0 } else { 1 },
+                warned_at: None,
+            },
+        };
+
+        sqlx::query(
+            "INSERT OR REPLACE INTO city_presence (lmo, city, last_seen_at, last_seen_report, last_report, missed_reports, warned_at) VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&presence.lmo)
+        .bind(&presence.city)
+        .bind(presence.last_seen_at)
+        .bind(&presence.last_seen_report)
+        .bind(&presence.last_report)
+        .bind(presence.missed_reports)
+        .bind(presence.warned_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(presence)
+    }
+
+    /// Remember that a missing city was notified so it is not notified again
+    pub async fn mark_city_missing_warned(&self, lmo: &str, city: &str) -> Result<()> {
+        sqlx::query("UPDATE city_presence SET warned_at = ? WHERE lmo = ? AND city = ?")
+            .bind(clock::now())
+            .bind(lmo)
+            .bind(city)
             .execute(&self.pool)
             .await?;
         Ok(())
     }
 
+    /// When a VPWW54 file not seen before was last recorded for an LMO
+    pub async fn get_last_new_vpww54(&self, lmo: &str) -> Result<Option<DateTime<Utc>>> {
+        let created_at = sqlx::query_scalar::<_, Option<DateTime<Utc>>>(
+            "SELECT MAX(created_at) FROM vpww54xml WHERE lmo = ?",
+        )
+        .bind(lmo)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(created_at)
+    }
+
     // VPWW54xml table operations
     #[allow(dead_code)]
     pub async fn get_vpww54_by_file(&self, xml_file: &str) -> Result<Option<VPWW54Xml>> {
@@ -126,14 +565,58 @@ impl Database {
     }
 
     pub async fn create_vpww54(&self, xml_file: &str, lmo: &str) -> Result<()> {
-        sqlx::query("INSERT INTO vpww54xml (xml_file, lmo) VALUES (?, ?)")
+        sqlx::query("INSERT INTO vpww54xml (xml_file, lmo, created_at) VALUES (?, ?, ?)")
+            .bind(xml_file)
+            .bind(lmo)
+            .bind(clock::now())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Record the checksum of a downloaded VPWW54 file, creating the record if needed
+    pub async fn record_vpww54_checksum(
+        &self,
+        lmo: &str,
+        xml_file: &str,
+        checksum: &str,
+    ) -> Result<()> {
+        let updated = sqlx::query(
+            "UPDATE vpww54xml SET checksum = ? WHERE xml_file = ? AND lmo = ? AND is_delete = 0",
+        )
+        .bind(checksum)
+        .bind(xml_file)
+        .bind(lmo)
+        .execute(&self.pool)
+        .await?;
+
+        if updated.rows_affected() == 0 {
+            sqlx::query(
+                "INSERT INTO vpww54xml (xml_file, lmo, checksum, created_at) VALUES (?, ?, ?, ?)",
+            )
             .bind(xml_file)
             .bind(lmo)
+            .bind(checksum)
+            .bind(clock::now())
             .execute(&self.pool)
             .await?;
+        }
+
         Ok(())
     }
 
+    /// Get the recorded checksum of a cached VPWW54 file, if any
+    pub async fn get_vpww54_checksum(&self, xml_file: &str) -> Result<Option<String>> {
+        let row = sqlx::query(
+            "SELECT checksum FROM vpww54xml WHERE xml_file = ? AND is_delete = 0 AND checksum IS NOT NULL ORDER BY id DESC LIMIT 1",
+        )
+        .bind(xml_file)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|r| r.get("checksum")))
+    }
+
     // CityReport table operations
     /// Get city report by lmo, city, and warning_kind
     /// Corresponds to Python's checkCityAndKindDataSameInCityReport()
@@ -154,28 +637,54 @@ impl Database {
         Ok(record)
     }
 
-    pub async fn create_city_report(&self, report: &CityReport) -> Result<()> {
-        sqlx::query(
-            "INSERT INTO city_report (xml_file, lmo, city, warning_kind, status) VALUES (?, ?, ?, ?, ?)"
-        )
-        .bind(&report.xml_file)
+    /// When the current active period of a record began
+    /// Taken from the latest `warning_event` that moved the kind into effect; records that
+    /// predate the event history fall back to their creation time.
+    pub async fn get_active_since(&self, report: &CityReport) -> Result<Option<DateTime<Utc>>> {
+        let started = sqlx::query_scalar::<_, DateTime<Utc>>(&format!(
+            "SELECT report_datetime FROM warning_event WHERE lmo = ? AND city = ? AND warning_kind = ? AND (previous_status IS NULL OR previous_status IN ({0})) AND new_status NOT IN ({0}) ORDER BY report_datetime DESC LIMIT 1",
+            inactive_statuses_sql()
+        ))
         .bind(&report.lmo)
         .bind(&report.city)
         .bind(&report.warning_kind)
-        .bind(&report.status)
-        .execute(&self.pool)
+        .fetch_optional(&self.pool)
         .await?;
-        Ok(())
+        Ok(started.or(report.created_at))
     }
 
-    pub async fn update_city_report(&self, id: i64, xml_file: &str, status: &str) -> Result<()> {
-        sqlx::query("UPDATE city_report SET xml_file = ?, status = ? WHERE id = ?")
-            .bind(xml_file)
-            .bind(status)
-            .bind(id)
-            .execute(&self.pool)
-            .await?;
-        Ok(())
+    /// Whether anyone acknowledged a notification about the record's current active period
+    /// Counts the notification that brought the kind into effect (via its `warning_event`)
+    /// and any reminder whose dedup key starts with `reminder_key_prefix`.
+    pub async fn is_period_acknowledged(
+        &self,
+        report: &CityReport,
+        reminder_key_prefix: &str,
+    ) -> Result<bool> {
+        let acknowledged = sqlx::query_scalar::<_, bool>(&format!(
+            "SELECT EXISTS (SELECT 1 FROM notification_ack a JOIN notification_outbox o ON o.id = a.outbox_id WHERE a.acked_at IS NOT NULL AND (o.dedup_key LIKE ? || '%' OR o.dedup_key = (SELECT notification_key FROM warning_event WHERE lmo = ? AND city = ? AND warning_kind = ? AND (previous_status IS NULL OR previous_status IN ({0})) AND new_status NOT IN ({0}) ORDER BY report_datetime DESC LIMIT 1)))",
+            inactive_statuses_sql()
+        ))
+        .bind(reminder_key_prefix)
+        .bind(&report.lmo)
+        .bind(&report.city)
+        .bind(&report.warning_kind)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(acknowledged)
+    }
+
+    /// Get all records for a city that are still in effect (active status and not deleted)
+    pub async fn get_active_city_reports(&self, lmo: &str, city: &str) -> Result<Vec<CityReport>> {
+        let records = sqlx::query_as::<_, CityReport>(&format!(
+            "SELECT * FROM city_report WHERE lmo = ? AND city = ? AND status NOT IN ({}) AND is_delete = 0",
+            inactive_statuses_sql()
+        ))
+        .bind(lmo)
+        .bind(city)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records)
     }
 
     #[allow(dead_code)]
@@ -189,30 +698,546 @@ impl Database {
 
     pub async fn delete_old_records(&self, days: i64) -> Result<()> {
         // Delete records older than specified days
+        // datetime() normalizes rows stored by older versions through CURRENT_TIMESTAMP
+        let cutoff = clock::now() - chrono::Duration::days(days);
+        sqlx::query(
+            "DELETE FROM city_report WHERE is_delete = 1 AND datetime(created_at) < datetime(?)",
+        )
+        .bind(cutoff)
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "DELETE FROM vpww54xml WHERE is_delete = 1 AND datetime(created_at) < datetime(?)",
+        )
+        .bind(cutoff)
+        .execute(&self.pool)
+        .await?;
+
+        // Held deliveries are kept until their digest is sent, along with their outbox rows
         sqlx::query(
-            "DELETE FROM city_report WHERE is_delete = 1 AND created_at < datetime('now', '-' || ? || ' days')"
+            "DELETE FROM notification_delivery WHERE status = 'sent' AND datetime(created_at) < datetime(?)",
         )
-        .bind(days)
+        .bind(cutoff)
         .execute(&self.pool)
         .await?;
 
         sqlx::query(
-            "DELETE FROM vpww54xml WHERE is_delete = 1 AND created_at < datetime('now', '-' || ? || ' days')"
+            "DELETE FROM notification_outbox WHERE status != 'pending' AND datetime(created_at) < datetime(?) AND id NOT IN (SELECT outbox_id FROM notification_delivery WHERE status = 'held')"
         )
-        .bind(days)
+        .bind(cutoff)
         .execute(&self.pool)
         .await?;
 
         Ok(())
     }
 
+    // ========================================================================
+    // Transactional outbox
+    // ========================================================================
+
+    /// Write a city's state changes from one report, their history events and its notification in one transaction
+    /// Changes announced by the notification share its key; changes filtered by notification
+    /// rules (or all of them, with no message) are marked "filtered". The VPWW54xml record
+    /// of a VPWW54 report is added too, so either all of the state, the `warning_event`
+    /// rows and the queued notification are stored, or none of it is.
+    pub async fn commit_city_report_changes(
+        &self,
+        changes: &[CityReportChange<'_>],
+        report_datetime: &DateTime<Utc>,
+        message: Option<&NewOutboxMessage>,
+    ) -> Result<()> {
+        let Some(first) = changes.first().map(|c| c.report) else {
+            return Ok(());
+        };
+
+        let mut tx = self.pool.begin().await?;
+
+        for change in changes {
+            let (previous, report) = (change.previous, change.report);
+            let key = message
+                .filter(|_| change.notified)
+                .map(|m| m.dedup_key.as_str());
+
+            match previous.and_then(|p| p.id) {
+                Some(id) => {
+                    sqlx::query("UPDATE city_report SET xml_file = ?, status = ? WHERE id = ?")
+                        .bind(&report.xml_file)
+                        .bind(&report.status)
+                        .bind(id)
+                        .execute(&mut *tx)
+                        .await?;
+                }
+                None => {
+                    sqlx::query(
+                        "INSERT INTO city_report (xml_file, lmo, city, warning_kind, status, created_at) VALUES (?, ?, ?, ?, ?, ?)"
+                    )
+                    .bind(&report.xml_file)
+                    .bind(&report.lmo)
+                    .bind(&report.city)
+                    .bind(&report.warning_kind)
+                    .bind(&report.status)
+                    .bind(clock::now())
+                    .execute(&mut *tx)
+                    .await?;
+                }
+            }
+
+            sqlx::query(
+                "INSERT INTO warning_event (lmo, city, warning_kind, previous_status, new_status, report_datetime, xml_file, notification_key, notification_status, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(&report.lmo)
+            .bind(&report.city)
+            .bind(&report.warning_kind)
+            .bind(previous.map(|p| p.status.as_str()))
+            .bind(&report.status)
+            .bind(report_datetime)
+            .bind(&report.xml_file)
+            .bind(key)
+            .bind(if key.is_some() { "queued" } else { "filtered" })
+            .bind(clock::now())
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        let xml_exists = sqlx::query(
+            "SELECT id FROM vpww54xml WHERE xml_file = ? AND lmo = ? AND is_delete = 0",
+        )
+        .bind(&first.xml_file)
+        .bind(&first.lmo)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        if xml_exists.is_none() && is_vpww54_file(&first.xml_file) {
+            sqlx::query("INSERT INTO vpww54xml (xml_file, lmo, created_at) VALUES (?, ?, ?)")
+                .bind(&first.xml_file)
+                .bind(&first.lmo)
+                .bind(clock::now())
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        if let Some(message) = message {
+            sqlx::query(
+                "INSERT OR IGNORE INTO notification_outbox (dedup_key, message_type, payload, next_attempt_at, created_at) VALUES (?, ?, ?, ?, ?)",
+            )
+            .bind(&message.dedup_key)
+            .bind(&message.message_type)
+            .bind(&message.payload)
+            .bind(clock::now())
+            .bind(clock::now())
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Get outbox messages queued after the given id, oldest first
+    pub async fn get_outbox_messages_after(&self, id: i64) -> Result<Vec<OutboxMessage>> {
+        let records = sqlx::query_as::<_, OutboxMessage>(
+            "SELECT * FROM notification_outbox WHERE id > ? ORDER BY id",
+        )
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records)
+    }
+
+    /// Get pending outbox messages that are due, oldest first
+    pub async fn get_due_outbox_messages(&self) -> Result<Vec<OutboxMessage>> {
+        let records = sqlx::query_as::<_, OutboxMessage>(
+            "SELECT * FROM notification_outbox WHERE status = 'pending' AND next_attempt_at <= ? ORDER BY id",
+        )
+        .bind(clock::now())
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records)
+    }
+
+    /// Queue a standalone notification (not tied to a state change)
+    /// Returns false when a message with the same dedup key was already queued.
+    pub async fn enqueue_outbox_message(&self, message: &NewOutboxMessage) -> Result<bool> {
+        let result = sqlx::query(
+            "INSERT OR IGNORE INTO notification_outbox (dedup_key, message_type, payload, next_attempt_at, created_at) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(&message.dedup_key)
+        .bind(&message.message_type)
+        .bind(&message.payload)
+        .bind(clock::now())
+        .bind(clock::now())
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    // ========================================================================
+    // Acknowledgements
+    // ========================================================================
+
+    /// Get the acknowledgement token of an outbox message, issuing one on first use
+    pub async fn ensure_ack_token(&self, outbox_id: i64, summary: &str) -> Result<String> {
+        if let Some(token) = sqlx::query_scalar::<_, String>(
+            "SELECT token FROM notification_ack WHERE outbox_id = ?",
+        )
+        .bind(outbox_id)
+        .fetch_optional(&self.pool)
+        .await?
+        {
+            return Ok(token);
+        }
+
+        let token = format!(
+            "{:016x}{:016x}",
+            rand::random::<u64>(),
+            rand::random::<u64>()
+        );
+        let now = clock::now();
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "INSERT INTO notification_ack (token, outbox_id, summary, created_at) VALUES (?, ?, ?, ?)",
+        )
+        .bind(&token)
+        .bind(outbox_id)
+        .bind(summary)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+        Self::audit(&mut tx, &token, "issued", None, Some(summary), now).await?;
+        tx.commit().await?;
+        Ok(token)
+    }
+
+    /// Look up an acknowledgement by token
+    pub async fn get_ack(&self, token: &str) -> Result<Option<NotificationAck>> {
+        let record =
+            sqlx::query_as::<_, NotificationAck>("SELECT * FROM notification_ack WHERE token = ?")
+                .bind(token)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(record)
+    }
+
+    /// Record an acknowledgement; every attempt, including repeats, is audited
+    pub async fn acknowledge(&self, token: &str, by: &str, detail: &str) -> Result<AckOutcome> {
+        let Some(ack) = self.get_ack(token).await? else {
+            return Ok(AckOutcome::UnknownToken);
+        };
+
+        let now = clock::now();
+        let mut tx = self.pool.begin().await?;
+        let outcome = if ack.acked_at.is_some() {
+            Self::audit(&mut tx, token, "repeat_ack", Some(by), Some(detail), now).await?;
+            AckOutcome::AlreadyAcknowledged(ack)
+        } else {
+            sqlx::query("UPDATE notification_ack SET acked_at = ?, acked_by = ? WHERE id = ?")
+                .bind(now)
+                .bind(by)
+                .bind(ack.id)
+                .execute(&mut *tx)
+                .await?;
+            Self::audit(&mut tx, token, "acknowledged", Some(by), Some(detail), now).await?;
+            AckOutcome::Recorded(NotificationAck {
+                acked_at: Some(now),
+                acked_by: Some(by.to_string()),
+                ..ack
+            })
+        };
+        tx.commit().await?;
+        Ok(outcome)
+    }
+
+    /// Unacknowledged, not yet escalated notifications issued before `cutoff`
+    pub async fn get_ack_overdue(&self, cutoff: DateTime<Utc>) -> Result<Vec<NotificationAck>> {
+        let records = sqlx::query_as::<_, NotificationAck>(
+            "SELECT * FROM notification_ack WHERE acked_at IS NULL AND escalated_at IS NULL AND created_at <= ? ORDER BY id",
+        )
+        .bind(cutoff)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records)
+    }
+
+    /// Mark a notification as escalated to the secondary recipients
+    pub async fn mark_ack_escalated(&self, ack: &NotificationAck, recipients: &str) -> Result<()> {
+        let now = clock::now();
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("UPDATE notification_ack SET escalated_at = ? WHERE id = ?")
+            .bind(now)
+            .bind(ack.id)
+            .execute(&mut *tx)
+            .await?;
+        Self::audit(
+            &mut tx,
+            &ack.token,
+            "escalated",
+            None,
+            Some(recipients),
+            now,
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn audit(
+        tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+        token: &str,
+        action: &str,
+        actor: Option<&str>,
+        detail: Option<&str>,
+        at: DateTime<Utc>,
+    ) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO ack_audit (token, action, actor, detail, created_at) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(token)
+        .bind(action)
+        .bind(actor)
+        .bind(detail)
+        .bind(at)
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+
+    /// Get an outbox message by id
+    pub async fn get_outbox_message(&self, id: i64) -> Result<Option<OutboxMessage>> {
+        let record =
+            sqlx::query_as::<_, OutboxMessage>("SELECT * FROM notification_outbox WHERE id = ?")
+                .bind(id)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(record)
+    }
+
+    /// Recipient groups that already received (or hold) an outbox message
+    pub async fn get_delivered_groups(&self, outbox_id: i64) -> Result<Vec<String>> {
+        let groups = sqlx::query_scalar::<_, String>(
+            "SELECT recipient_group FROM notification_delivery WHERE outbox_id = ?",
+        )
+        .bind(outbox_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(groups)
+    }
+
+    /// Record that an outbox message was sent to, or held for, a recipient group
+    pub async fn record_delivery(&self, outbox_id: i64, group: &str, held: bool) -> Result<()> {
+        let now = clock::now();
+        sqlx::query(
+            "INSERT OR IGNORE INTO notification_delivery (outbox_id, recipient_group, status, sent_at, created_at) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(outbox_id)
+        .bind(group)
+        .bind(if held { "held" } else { "sent" })
+        .bind((!held).then_some(now))
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Notifications held for a recipient group's digest, oldest first
+    pub async fn get_held_deliveries(&self, group: &str) -> Result<Vec<HeldDelivery>> {
+        let records = sqlx::query_as::<_, HeldDelivery>(
+            "SELECT d.id, o.dedup_key, o.message_type, o.payload FROM notification_delivery d JOIN notification_outbox o ON o.id = d.outbox_id WHERE d.recipient_group = ? AND d.status = 'held' ORDER BY d.outbox_id",
+        )
+        .bind(group)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records)
+    }
+
+    /// Mark held deliveries as sent once their digest went out
+    /// An outbox message no longer held for any group is marked sent.
+    pub async fn mark_held_sent(&self, ids: &[i64]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        let now = clock::now();
+        let mut completed = Vec::new();
+        for id in ids {
+            sqlx::query(
+                "UPDATE notification_delivery SET status = 'sent', sent_at = ? WHERE id = ?",
+            )
+            .bind(now)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+
+            let outbox_id: i64 =
+                sqlx::query_scalar("SELECT outbox_id FROM notification_delivery WHERE id = ?")
+                    .bind(id)
+                    .fetch_one(&mut *tx)
+                    .await?;
+            let result = sqlx::query(
+                "UPDATE notification_outbox SET status = 'sent', sent_at = ? WHERE id = ? AND status = 'held' AND NOT EXISTS (SELECT 1 FROM notification_delivery WHERE outbox_id = ? AND status = 'held')",
+            )
+            .bind(now)
+            .bind(outbox_id)
+            .bind(outbox_id)
+            .execute(&mut *tx)
+            .await?;
+            if result.rows_affected() > 0 {
+                completed.push(outbox_id);
+            }
+        }
+        tx.commit().await?;
+
+        for outbox_id in completed {
+            self.update_event_notification_status(outbox_id).await?;
+        }
+        Ok(())
+    }
+
+    /// Mark an outbox message as delivered to every group it is due for
+    /// A message still held for a group's digest is marked `held` instead;
+    /// `mark_held_sent` marks it sent once the last held delivery went out.
+    pub async fn mark_outbox_sent(&self, id: i64) -> Result<()> {
+        sqlx::query(
+            "UPDATE notification_outbox SET status = CASE WHEN EXISTS (SELECT 1 FROM notification_delivery WHERE outbox_id = ? AND status = 'held') THEN 'held' ELSE 'sent' END, sent_at = ?, attempts = attempts + 1, last_error = NULL WHERE id = ?",
+        )
+        .bind(id)
+        .bind(clock::now())
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+        self.update_event_notification_status(id).await
+    }
+
+    /// Copy an outbox message's delivery status onto the warning events it announces
+    async fn update_event_notification_status(&self, outbox_id: i64) -> Result<()> {
+        sqlx::query(
+            "UPDATE warning_event SET notification_status = (SELECT status FROM notification_outbox WHERE id = ?) WHERE notification_key = (SELECT dedup_key FROM notification_outbox WHERE id = ?)",
+        )
+        .bind(outbox_id)
+        .bind(outbox_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Record a failed delivery attempt
+    /// `next_attempt_at` schedules the retry; `None` gives up and marks the message failed.
+    pub async fn mark_outbox_attempt_failed(
+        &self,
+        id: i64,
+        error: &str,
+        next_attempt_at: Option<DateTime<Utc>>,
+    ) -> Result<()> {
+        sqlx::query(
+            "UPDATE notification_outbox SET attempts = attempts + 1, last_error = ?, next_attempt_at = ?, status = ? WHERE id = ?",
+        )
+        .bind(error)
+        .bind(next_attempt_at)
+        .bind(if next_attempt_at.is_some() { "pending" } else { "failed" })
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        if next_attempt_at.is_none() {
+            self.update_event_notification_status(id).await?;
+        }
+        Ok(())
+    }
+
+    // ========================================================================
+    // Warning event history
+    // ========================================================================
+
+    /// Get every transition for a city of an LMO, oldest first, optionally limited to a
+    /// time range and a single warning kind
+    pub async fn get_city_timeline(
+        &self,
+        lmo: &str,
+        city: &str,
+        warning_kind: Option<&str>,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<Vec<WarningEvent>> {
+        let records = sqlx::query_as::<_, WarningEvent>(
+            "SELECT * FROM warning_event WHERE lmo = ? AND city = ? AND (? IS NULL OR warning_kind = ?) AND (? IS NULL OR report_datetime >= ?) AND (? IS NULL OR report_datetime <= ?) ORDER BY report_datetime, id",
+        )
+        .bind(lmo)
+        .bind(city)
+        .bind(warning_kind)
+        .bind(warning_kind)
+        .bind(since)
+        .bind(since)
+        .bind(until)
+        .bind(until)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records)
+    }
+
+    /// Get the periods during which warnings were in effect for a city of an LMO
+    /// A period starts when a kind moves into an active status (発表, 継続, ...) and ends
+    /// at 解除 or "発表警報・注意報はなし". Periods overlapping the range are included.
+    pub async fn get_warning_periods(
+        &self,
+        lmo: &str,
+        city: &str,
+        warning_kind: Option<&str>,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<Vec<WarningPeriod>> {
+        // Start from the full history up to `until` so periods opened before `since` are found
+        let events = self
+            .get_city_timeline(lmo, city, warning_kind, None, until)
+            .await?;
+
+        let mut open: std::collections::HashMap<(String, String), WarningPeriod> =
+            std::collections::HashMap::new();
+        let mut periods = Vec::new();
+
+        for event in events {
+            let key = (event.lmo.clone(), event.warning_kind.clone());
+            let active = is_active_status(&event.new_status);
+
+            match (open.contains_key(&key), active) {
+                (false, true) => {
+                    open.insert(
+                        key,
+                        WarningPeriod {
+                            lmo: event.lmo,
+                            city: event.city,
+                            warning_kind: event.warning_kind,
+                            started_at: event.report_datetime,
+                            ended_at: None,
+                        },
+                    );
+                }
+                (true, false) => {
+                    if let Some(mut period) = open.remove(&key) {
+                        period.ended_at = Some(event.report_datetime);
+                        periods.push(period);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        periods.extend(open.into_values());
+        periods.retain(|p| match (since, p.ended_at) {
+            (Some(since), Some(end)) => end >= since,
+            _ => true,
+        });
+        periods.sort_by_key(|p| p.started_at);
+        Ok(periods)
+    }
+
     // ========================================================================
     // New methods to match Python implementation
     // ========================================================================
 
     /// Add VPWW54xml record if not exists
-    /// Corresponds to Python's addVPWW54xml()
+    /// Corresponds to Python's addVPWW54xml(); JSON report ids are not recorded.
     pub async fn add_vpww54_xml(&self, lmo: &str, xml_file: &str) -> Result<()> {
+        if !is_vpww54_file(xml_file) {
+            return Ok(());
+        }
+
         // Check if already exists
         let exists = sqlx::query(
             "SELECT id FROM vpww54xml WHERE xml_file = ? AND lmo = ? AND is_delete = 0",
@@ -261,17 +1286,39 @@ impl Database {
 
     /// Delete city reports by status (soft delete all reports for a city)
     /// Corresponds to Python's deleteCityReportByStatus()
-    pub async fn delete_city_reports_by_city(&self, lmo: &str, city: &str) -> Result<()> {
+    /// Warnings still in effect are logged to `warning_event` as ending with this report.
+    pub async fn delete_city_reports_by_city(
+        &self,
+        lmo: &str,
+        city: &str,
+        xml_file: &str,
+        report_datetime: &DateTime<Utc>,
+    ) -> Result<()> {
         tracing::info!("Deleting all reports for {} - {}", lmo, city);
 
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(&format!(
+            "INSERT INTO warning_event (lmo, city, warning_kind, previous_status, new_status, report_datetime, xml_file, created_at) SELECT lmo, city, warning_kind, status, '発表警報・注意報はなし', ?, ?, ? FROM city_report WHERE lmo = ? AND city = ? AND is_delete = 0 AND status NOT IN ({})",
+            inactive_statuses_sql()
+        ))
+        .bind(report_datetime)
+        .bind(xml_file)
+        .bind(clock::now())
+        .bind(lmo)
+        .bind(city)
+        .execute(&mut *tx)
+        .await?;
+
         sqlx::query(
             "UPDATE city_report SET is_delete = 1 WHERE lmo = ? AND city = ? AND is_delete = 0",
         )
         .bind(lmo)
         .bind(city)
-        .execute(&self.pool)
+        .execute(&mut *tx)
         .await?;
 
+        tx.commit().await?;
         Ok(())
     }
 
@@ -0,0 +1,260 @@
+use crate::clock;
+use crate::config::{AckConfig, Config, MonitorConfig, RecipientGroup};
+use crate::database::{Database, OutboxMessage};
+use crate::error::{Result, WeatherCheckerError};
+use crate::notification::{EmailNotifier, Notification};
+use chrono::{DateTime, Duration, Utc};
+use tokio::sync::Mutex;
+
+/// Give up on a message after this many failed attempts
+const MAX_ATTEMPTS: i64 = 10;
+/// Retry delay after the first failure; doubles per attempt up to `MAX_RETRY_DELAY_SECS`
+const BASE_RETRY_DELAY_SECS: i64 = 60;
+const MAX_RETRY_DELAY_SECS: i64 = 3600;
+
+/// Prevents two dispatch runs from sending the same message concurrently
+static DISPATCH_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// Delivers queued notifications from the outbox table to every recipient group
+pub struct Dispatcher<'a> {
+    db: &'a Database,
+    notifier: &'a EmailNotifier,
+    groups: &'a [RecipientGroup],
+    ack: Option<&'a AckConfig>,
+}
+
+impl<'a> Dispatcher<'a> {
+    pub fn new(
+        db: &'a Database,
+        notifier: &'a EmailNotifier,
+        groups: &'a [RecipientGroup],
+    ) -> Self {
+        Self {
+            db,
+            notifier,
+            groups,
+            ack: None,
+        }
+    }
+
+    /// Attach acknowledgement links to 警報-level notifications
+    pub fn with_ack(mut self, ack: Option<&'a AckConfig>) -> Self {
+        self.ack = ack;
+        self
+    }
+
+    /// Deliver all due messages in enqueue order, then any quiet-hours digests now due
+    /// Stops at the first delivery failure so later messages never overtake earlier ones;
+    /// the failed message is retried with exponential backoff until `MAX_ATTEMPTS`.
+    /// Messages that can never be delivered (undecodable payload, invalid address) are
+    /// marked failed at once and do not block the queue.
+    /// Groups that already received a message are not sent it again on retry.
+    pub async fn dispatch_pending(&self) -> Result<usize> {
+        let _guard = DISPATCH_LOCK.lock().await;
+
+        let messages = self.db.get_due_outbox_messages().await?;
+        let mut sent = 0;
+
+        for message in messages {
+            match self.deliver(&message).await {
+                Ok(()) => {
+                    self.db.mark_outbox_sent(message.id).await?;
+                    sent += 1;
+                }
+                Err(e) if is_permanent(&e) => {
+                    tracing::error!(
+                        "Delivery of {} cannot succeed, giving up: {}",
+                        message.dedup_key,
+                        e
+                    );
+                    self.db
+                        .mark_outbox_attempt_failed(message.id, &e.to_string(), None)
+                        .await?;
+                }
+                Err(e) => {
+                    let attempts = message.attempts + 1;
+                    let next_attempt_at = (attempts < MAX_ATTEMPTS).then(|| {
+                        let delay = (BASE_RETRY_DELAY_SECS << (attempts - 1).min(20))
+                            .min(MAX_RETRY_DELAY_SECS);
+                        clock::now() + Duration::seconds(delay)
+                    });
+
+                    match next_attempt_at {
+                        Some(at) => tracing::warn!(
+                            "Delivery of {} failed (attempt {}), retrying at {}: {}",
+                            message.dedup_key,
+                            attempts,
+                            at,
+                            e
+                        ),
+                        None => tracing::error!(
+                            "Delivery of {} failed {} times, giving up: {}",
+                            message.dedup_key,
+                            attempts,
+                            e
+                        ),
+                    }
+
+                    self.db
+                        .mark_outbox_attempt_failed(message.id, &e.to_string(), next_attempt_at)
+                        .await?;
+
+                    if next_attempt_at.is_some() {
+                        break;
+                    }
+                }
+            }
+        }
+
+        if sent > 0 {
+            tracing::info!("Dispatched {} queued notifications", sent);
+        }
+
+        self.send_due_digests().await?;
+        Ok(sent)
+    }
+
+    async fn deliver(&self, message: &OutboxMessage) -> Result<()> {
+        let notification = decode(&message.message_type, &message.payload, &message.dedup_key)?;
+        let delivered = self.db.get_delivered_groups(message.id).await?;
+        let now = clock::now();
+
+        // One token per message, shared by every group; issued before the first send
+        let ack_url = match self.ack {
+            Some(ack) if notification.needs_ack() => {
+                let token = self
+                    .db
+                    .ensure_ack_token(message.id, &notification.subject())
+                    .await?;
+                Some(ack.ack_url(&token))
+            }
+            _ => None,
+        };
+
+        // A 特別警報 goes to every group at once, including the emergency-only ones
+        let emergency = notification.is_emergency();
+
+        for group in self.groups {
+            if delivered.contains(&group.name) || (group.emergency_only && !emergency) {
+                continue;
+            }
+            if holds(group, &notification, now) {
+                tracing::info!(
+                    "Holding {} for {} until quiet hours end",
+                    message.dedup_key,
+                    group.name
+                );
+                self.db
+                    .record_delivery(message.id, &group.name, true)
+                    .await?;
+            } else {
+                self.notifier
+                    .send_warning_notification(&notification, group, ack_url.as_deref())
+                    .await?;
+                self.db
+                    .record_delivery(message.id, &group.name, false)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Send one digest per group whose quiet hours are over and that has held notifications
+    /// A failed digest stays held and is retried on the next dispatch run.
+    async fn send_due_digests(&self) -> Result<()> {
+        let now = clock::now();
+
+        for group in self.groups {
+            if group.quiet_hours.as_ref().is_some_and(|q| q.contains(now)) {
+                continue;
+            }
+
+            let held = self.db.get_held_deliveries(&group.name).await?;
+            if held.is_empty() {
+                continue;
+            }
+
+            let mut notifications = Vec::with_capacity(held.len());
+            for delivery in &held {
+                match decode(
+                    &delivery.message_type,
+                    &delivery.payload,
+                    &delivery.dedup_key,
+                ) {
+                    Ok(notification) => notifications.push(notification),
+                    Err(e) => tracing::error!("Dropping held notification: {}", e),
+                }
+            }
+
+            if !notifications.is_empty() {
+                if let Err(e) = self
+                    .notifier
+                    .send_warning_digest(&notifications, group)
+                    .await
+                {
+                    tracing::warn!("Digest for {} failed, will retry: {}", group.name, e);
+                    continue;
+                }
+            }
+
+            let ids: Vec<i64> = held.iter().map(|d| d.id).collect();
+            self.db.mark_held_sent(&ids).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether a notification is held for the group's digest rather than sent now
+/// Only kinds below the group's `hold_below` severity are held, and only inside quiet
+/// hours; notifications of unknown severity always go out immediately.
+fn holds(group: &RecipientGroup, notification: &Notification, now: DateTime<Utc>) -> bool {
+    let Some(quiet_hours) = &group.quiet_hours else {
+        return false;
+    };
+    quiet_hours.contains(now)
+        && notification
+            .severity()
+            .is_some_and(|severity| severity < quiet_hours.hold_below)
+}
+
+/// Decode an outbox payload into a notification
+fn decode(message_type: &str, payload: &str, dedup_key: &str) -> Result<Notification> {
+    match Notification::decode(message_type, payload) {
+        Some(Ok(notification)) => Ok(notification),
+        Some(Err(e)) => Err(WeatherCheckerError::Payload(format!(
+            "Invalid outbox payload for {}: {}",
+            dedup_key, e
+        ))),
+        None => Err(WeatherCheckerError::Payload(format!(
+            "Unknown outbox message type: {}",
+            message_type
+        ))),
+    }
+}
+
+/// Whether a delivery error is caused by the message itself, so retrying cannot help
+/// Transport, SMTP and database errors are transient and retried with backoff.
+fn is_permanent(error: &WeatherCheckerError) -> bool {
+    matches!(
+        error,
+        WeatherCheckerError::Payload(_)
+            | WeatherCheckerError::Address(_)
+            | WeatherCheckerError::Email(_)
+    )
+}
+
+/// Standalone dispatch run used by the scheduler's retry job
+pub async fn run_dispatch() -> Result<()> {
+    let config = Config::from_env()?;
+    let monitor_config = MonitorConfig::load(MonitorConfig::default_path())?;
+    let groups = monitor_config.recipient_groups(&config);
+    let ack = config.ack.clone();
+    let db = Database::new().await?;
+    let notifier = EmailNotifier::new(config);
+    Dispatcher::new(&db, &notifier, &groups)
+        .with_ack(ack.as_ref())
+        .dispatch_pending()
+        .await?;
+    Ok(())
+}
@@ -11,6 +11,12 @@ pub enum WeatherCheckerError {
     #[error("Email error: {0}")]
     Email(#[from] lettre::error::Error),
 
+    #[error("Address error: {0}")]
+    Address(#[from] lettre::address::AddressError),
+
+    #[error("Invalid notification: {0}")]
+    Payload(String),
+
     #[error("XML parsing error: {0}")]
     XmlParse(String),
 
@@ -33,12 +39,6 @@ impl From<tokio_cron_scheduler::JobSchedulerError> for WeatherCheckerError {
     }
 }
 
-impl From<lettre::address::AddressError> for WeatherCheckerError {
-    fn from(err: lettre::address::AddressError) -> Self {
-        WeatherCheckerError::Other(format!("Address error: {}", err))
-    }
-}
-
 impl From<lettre::transport::smtp::Error> for WeatherCheckerError {
     fn from(err: lettre::transport::smtp::Error) -> Self {
         WeatherCheckerError::Other(format!("SMTP error: {}", err))
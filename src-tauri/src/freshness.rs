@@ -0,0 +1,113 @@
+use crate::clock;
+use crate::config::{Config, MonitorConfig, SourceKind};
+use crate::database::Database;
+use crate::error::Result;
+use crate::jma_feed::EXTRA_FEED;
+use crate::notification::EmailNotifier;
+use chrono::{DateTime, Duration, Utc};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether the feed was stale at the last check, so each transition is notified once
+static FEED_STALE: AtomicBool = AtomicBool::new(false);
+
+/// How recent the JMA data seen by the checker is
+#[derive(Debug)]
+pub struct Freshness {
+    pub checked_at: DateTime<Utc>,
+    /// Newest feed-level `<updated>` of extra.xml (polled or pushed)
+    pub feed_updated: Option<DateTime<Utc>>,
+    /// When a new VPWW54 was last seen for each LMO on the XML source
+    pub lmo_updated: Vec<(String, Option<DateTime<Utc>>)>,
+    /// Silence after which the feed counts as stale; `None` when detection is off
+    pub threshold: Option<Duration>,
+}
+
+impl Freshness {
+    /// True when the feed has been silent longer than the threshold
+    /// A feed never seen yet is not stale: nothing has been fetched to compare against.
+    pub fn is_stale(&self) -> bool {
+        match (self.threshold, self.feed_updated) {
+            (Some(threshold), Some(updated)) => self.checked_at - updated > threshold,
+            _ => false,
+        }
+    }
+
+    /// One-line summary for the heartbeat file and notifications
+    /// e.g. `freshness=ok extra.xml=12m 静岡地方気象台=95m`
+    pub fn summary(&self) -> String {
+        let state = if self.is_stale() { "stale" } else { "ok" };
+        let mut summary = format!(
+            "freshness={} {}={}",
+            state,
+            EXTRA_FEED,
+            self.age(self.feed_updated)
+        );
+        for (lmo, updated) in &self.lmo_updated {
+            summary.push_str(&format!(" {}={}", lmo, self.age(*updated)));
+        }
+        summary
+    }
+
+    fn age(&self, updated: Option<DateTime<Utc>>) -> String {
+        match updated {
+            Some(updated) => format!("{}m", (self.checked_at - updated).num_minutes()),
+            None => "unknown".to_string(),
+        }
+    }
+}
+
+/// Measure data freshness and notify when the feed goes stale or recovers
+/// Returns the summary for the heartbeat. Only the extra.xml age is alerted on; a single
+/// LMO can legitimately go days without a new VPWW54, so those ages are reported only.
+pub async fn run_freshness_check() -> Result<String> {
+    let config = Config::from_env()?;
+    let monitor_config = MonitorConfig::load(MonitorConfig::default_path())?;
+    let db = Database::new().await?;
+
+    let mut lmo_updated: Vec<(String, Option<DateTime<Utc>>)> = Vec::new();
+    for region in &monitor_config.monitored_regions {
+        if region.source != SourceKind::Xml || lmo_updated.iter().any(|(l, _)| *l == region.lmo) {
+            continue;
+        }
+        lmo_updated.push((
+            region.lmo.clone(),
+            db.get_last_new_vpww54(&region.lmo).await?,
+        ));
+    }
+
+    let freshness = Freshness {
+        checked_at: clock::now(),
+        feed_updated: db.get_feed_updated(EXTRA_FEED).await?,
+        lmo_updated,
+        threshold: (config.feed_stale_minutes > 0)
+            .then(|| Duration::minutes(config.feed_stale_minutes as i64)),
+    };
+    let summary = freshness.summary();
+
+    let stale = freshness.is_stale();
+    if FEED_STALE.swap(stale, Ordering::Relaxed) != stale {
+        let (event, details) = if stale {
+            tracing::warn!("JMA feed is stale: {}", summary);
+            (
+                "feed stale",
+                format!(
+                    "extra.xml has not been updated for over {} minutes ({})",
+                    config.feed_stale_minutes, summary
+                ),
+            )
+        } else {
+            tracing::info!("JMA feed is updating again: {}", summary);
+            (
+                "feed recovered",
+                format!("extra.xml is updating again ({})", summary),
+            )
+        };
+
+        let notifier = EmailNotifier::new(config);
+        if let Err(e) = notifier.send_system_notification(event, &details).await {
+            tracing::warn!("Failed to send {} notification: {}", event, e);
+        }
+    }
+
+    Ok(summary)
+}
@@ -1,15 +1,36 @@
+use crate::clock;
 use crate::config::Config;
 use crate::database::Database;
-use crate::error::Result;
+use crate::error::{Result, WeatherCheckerError};
 use chrono::{DateTime, Utc};
-use reqwest::Client;
+use rand::Rng;
+use reqwest::{Certificate, Client, NoProxy, Proxy, RequestBuilder, Response, StatusCode};
 use serde::Deserialize;
+use sha2::{Digest, Sha256};
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::OnceCell;
+
+/// JMA "extra" (随時) Atom feed, also the WebSub topic for push delivery
+pub const EXTRA_XML_URL: &str = "https://www.data.jma.go.jp/developer/xml/feed/extra.xml";
+
+/// Feed name under which extra.xml freshness is recorded
+pub const EXTRA_FEED: &str = "extra.xml";
+
+/// Base URL of the bosai warning JSON, one document per office code
+const BOSAI_WARNING_URL: &str = "https://www.jma.go.jp/bosai/warning/data/warning";
+
+/// Upper bound for a single backoff delay between retries
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
 
 #[derive(Debug, Clone)]
 pub struct JMAFeed {
     client: Client,
     config: Config,
+    /// extra.xml content, fetched at most once per JMAFeed (i.e. per check cycle)
+    /// so concurrent region checks share one conditional request and cache write
+    extra_xml: Arc<OnceCell<Option<Vec<u8>>>>,
 }
 
 // ============================================================================
@@ -86,8 +107,42 @@ pub struct WarningKind {
     pub status: String,
 }
 
+// ============================================================================
+// Data structures for bosai warning JSON
+// ============================================================================
+
+/// bosai/warning/data/warning/<office>.json document
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BosaiWarningReport {
+    pub report_datetime: DateTime<chrono::FixedOffset>,
+    pub publishing_office: String,
+    pub area_types: Vec<BosaiAreaType>,
+}
+
+/// One area granularity (class10s, class20s, ...) within a bosai report
+#[derive(Debug, Clone, Deserialize)]
+pub struct BosaiAreaType {
+    pub areas: Vec<BosaiArea>,
+}
+
+/// Warnings in effect for one area code
+#[derive(Debug, Clone, Deserialize)]
+pub struct BosaiArea {
+    pub code: String,
+    #[serde(default)]
+    pub warnings: Vec<BosaiWarning>,
+}
+
+/// A single warning entry; `code` is absent for "発表警報・注意報はなし"
+#[derive(Debug, Clone, Deserialize)]
+pub struct BosaiWarning {
+    pub code: Option<String>,
+    pub status: String,
+}
+
 // Legacy structure for backward compatibility
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct WarningData {
     pub city: String,
     pub warning_kind: String,
@@ -95,53 +150,198 @@ pub struct WarningData {
 }
 
 impl JMAFeed {
-    pub fn new(config: Config) -> Self {
-        Self {
-            client: Client::new(),
+    pub fn new(config: Config) -> Result<Self> {
+        let mut builder = Client::builder()
+            .connect_timeout(Duration::from_secs(config.http_connect_timeout_secs))
+            .timeout(Duration::from_secs(config.http_timeout_secs))
+            .user_agent(config.http_user_agent.clone());
+
+        if let Some(proxy_url) = &config.proxy_url {
+            let proxy = Proxy::all(proxy_url)?
+                .no_proxy(config.no_proxy.as_deref().and_then(NoProxy::from_string));
+            tracing::debug!("Using proxy for JMA requests: {}", proxy_url);
+            builder = builder.proxy(proxy);
+        }
+
+        for pem in config.load_extra_ca_pems()? {
+            for cert in Certificate::from_pem_bundle(&pem)? {
+                builder = builder.add_root_certificate(cert);
+            }
+        }
+
+        let client = builder.build()?;
+
+        Ok(Self {
+            client,
             config,
+            extra_xml: Arc::new(OnceCell::new()),
+        })
+    }
+
+    /// Send a request, retrying transient failures with exponential backoff and jitter
+    /// Transient failures are timeouts, connection errors, 429 and 5xx responses.
+    /// Any other response (including 304 and 4xx) is returned to the caller as-is.
+    async fn send_with_retry<F>(&self, build_request: F) -> Result<Response>
+    where
+        F: Fn() -> RequestBuilder,
+    {
+        let mut attempt = 0;
+
+        loop {
+            let outcome = build_request().send().await;
+
+            let retryable = match &outcome {
+                Ok(response) => is_transient_status(response.status()),
+                Err(e) => e.is_timeout() || e.is_connect(),
+            };
+
+            if !retryable || attempt >= self.config.http_max_retries {
+                return match outcome {
+                    Ok(response) if is_transient_status(response.status()) => {
+                        Ok(response.error_for_status()?)
+                    }
+                    Ok(response) => Ok(response),
+                    Err(e) => Err(e.into()),
+                };
+            }
+
+            let delay = self.retry_delay(attempt);
+            match &outcome {
+                Ok(response) => tracing::warn!(
+                    "Transient HTTP status {} from {}, retrying in {}ms (attempt {}/{})",
+                    response.status(),
+                    response.url(),
+                    delay.as_millis(),
+                    attempt + 1,
+                    self.config.http_max_retries
+                ),
+                Err(e) => tracing::warn!(
+                    "HTTP request failed: {}, retrying in {}ms (attempt {}/{})",
+                    e,
+                    delay.as_millis(),
+                    attempt + 1,
+                    self.config.http_max_retries
+                ),
+            }
+
+            tokio::time::sleep(delay).await;
+            attempt += 1;
         }
     }
 
+    /// Fetch the bosai warning JSON for a JMA office code (e.g. "220000")
+    pub async fn fetch_bosai_warning(&self, office_code: &str) -> Result<BosaiWarningReport> {
+        let url = format!("{}/{}.json", BOSAI_WARNING_URL, office_code);
+
+        let response = self
+            .send_with_retry(|| self.client.get(&url))
+            .await?
+            .error_for_status()?;
+
+        let report = response.json::<BosaiWarningReport>().await?;
+        tracing::debug!(
+            "Fetched bosai warning JSON for {} ({}, reported {})",
+            office_code,
+            report.publishing_office,
+            report.report_datetime
+        );
+        Ok(report)
+    }
+
+    /// Ask a WebSub hub to (re)subscribe our callback to a topic
+    /// The hub confirms asynchronously by calling the callback with a verification challenge.
+    pub async fn websub_subscribe(
+        &self,
+        hub_url: &str,
+        topic_url: &str,
+        callback_url: &str,
+        secret: &str,
+        lease_seconds: u64,
+    ) -> Result<()> {
+        let lease = lease_seconds.to_string();
+        let params = [
+            ("hub.mode", "subscribe"),
+            ("hub.topic", topic_url),
+            ("hub.callback", callback_url),
+            ("hub.secret", secret),
+            ("hub.lease_seconds", lease.as_str()),
+        ];
+
+        self.send_with_retry(|| self.client.post(hub_url).form(&params))
+            .await?
+            .error_for_status()?;
+
+        tracing::info!(
+            "Requested WebSub subscription to {} via {}",
+            topic_url,
+            hub_url
+        );
+        Ok(())
+    }
+
+    /// Exponential backoff with full jitter: a random delay in [0, base * 2^attempt]
+    fn retry_delay(&self, attempt: u32) -> Duration {
+        let ceiling = Duration::from_millis(self.config.http_retry_base_ms)
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(MAX_RETRY_DELAY);
+        let ceiling_ms = ceiling.as_millis() as u64;
+        Duration::from_millis(rand::thread_rng().gen_range(0..=ceiling_ms))
+    }
+
     /// Fetch the extra.xml feed with If-Modified-Since header
     /// Saves the file to cache directory for future use
     pub async fn fetch_extra_xml(&self, db: &Database) -> Result<Option<Vec<u8>>> {
-        let url = "https://www.data.jma.go.jp/developer/xml/feed/extra.xml";
+        let url = EXTRA_XML_URL;
         let cache_path = PathBuf::from(&self.config.data_dir).join("extra.xml");
 
-        let mut request = self.client.get(url);
-
         // Add If-Modified-Since header if we have a previous Last-Modified value
-        if let Some(last_modified) = db.get_extra_last_modified().await? {
+        let last_modified = db.get_extra_last_modified().await?;
+        if let Some(last_modified) = &last_modified {
             tracing::debug!(
                 "Requesting extra.xml with If-Modified-Since: {}",
                 last_modified
             );
-            request = request.header("If-Modified-Since", last_modified);
         }
 
-        let response = request.send().await?;
+        let response = self
+            .send_with_retry(|| {
+                let request = self.client.get(url);
+                match &last_modified {
+                    Some(value) => request.header("If-Modified-Since", value),
+                    None => request,
+                }
+            })
+            .await?;
 
         // If 304 Not Modified, return None
-        if response.status() == 304 {
+        if response.status() == StatusCode::NOT_MODIFIED {
             tracing::debug!("Extra.xml not modified (304)");
             return Ok(None);
         }
 
-        // Store Last-Modified header for next request
-        if let Some(last_modified) = response.headers().get("Last-Modified") {
-            if let Ok(last_modified_str) = last_modified.to_str() {
-                db.update_extra(last_modified_str).await?;
-                tracing::debug!("Updated Last-Modified: {}", last_modified_str);
-            }
-        }
+        // Never cache an error page as the feed
+        let response = response.error_for_status()?;
 
+        let last_modified = response
+            .headers()
+            .get("Last-Modified")
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
         let content = response.bytes().await?.to_vec();
 
-        // Save to cache
+        // Save to cache atomically: write a temp file, then rename over the final name
         std::fs::create_dir_all(&self.config.data_dir)?;
-        std::fs::write(&cache_path, &content)?;
+        let tmp_path = cache_path.with_extension(format!("{:016x}.tmp", rand::random::<u64>()));
+        std::fs::write(&tmp_path, &content)?;
+        std::fs::rename(&tmp_path, &cache_path)?;
         tracing::debug!("Saved extra.xml to cache");
 
+        // Store Last-Modified only once the body is cached, so a failed read is retried
+        if let Some(last_modified) = last_modified {
+            db.update_extra(&last_modified).await?;
+            tracing::debug!("Updated Last-Modified: {}", last_modified);
+        }
+
         Ok(Some(content))
     }
 
@@ -174,7 +374,7 @@ impl JMAFeed {
                         current_entry = Some(FeedEntry {
                             title: String::new(),
                             id: String::new(),
-                            updated: Utc::now(),
+                            updated: clock::now(),
                             author_name: String::new(),
                             link: String::new(),
                         });
@@ -254,7 +454,7 @@ impl JMAFeed {
                         reader.buffer_position(),
                         e
                     );
-                    return Err(crate::error::WeatherCheckerError::XmlParse(format!(
+                    return Err(WeatherCheckerError::XmlParse(format!(
                         "XML parse error: {}",
                         e
                     )));
@@ -265,31 +465,86 @@ impl JMAFeed {
         }
 
         // Sort by updated time (newest first)
-        entries.sort_by(|a, b| b.updated.cmp(&a.updated));
+        entries.sort_by_key(|e| std::cmp::Reverse(e.updated));
 
         tracing::debug!("Parsed {} VPWW54 entries from extra.xml", entries.len());
         Ok(entries)
     }
 
     /// Download and parse a VPWW54 XML file
+    /// A cached copy is reused only if it still matches the checksum recorded in
+    /// `vpww54xml` and parses as a VPWW54 report; otherwise it is discarded and re-downloaded.
     /// Returns (warnings, control_datetime) where control_datetime is from <Control><DateTime>
-    pub async fn fetch_vpww54(&self, url: &str, filename: &str) -> Result<(Vec<WarningData>, DateTime<Utc>)> {
+    pub async fn fetch_vpww54(
+        &self,
+        db: &Database,
+        lmo: &str,
+        url: &str,
+        filename: &str,
+    ) -> Result<(Vec<WarningData>, DateTime<Utc>)> {
         // Check if file already exists in cache
         let file_path = PathBuf::from(&self.config.data_dir).join(filename);
 
         if file_path.exists() {
-            tracing::debug!("Using cached VPWW54 file: {}", filename);
-            let content = std::fs::read_to_string(&file_path)?;
-            return self.parse_vpww54(&content);
+            match self.read_cached_vpww54(db, &file_path, filename).await {
+                Ok(parsed) => {
+                    tracing::debug!("Using cached VPWW54 file: {}", filename);
+                    return Ok(parsed);
+                }
+                Err(e) => {
+                    tracing::warn!("Discarding invalid cached VPWW54 file {}: {}", filename, e);
+                    std::fs::remove_file(&file_path)?;
+                }
+            }
         }
 
-        // Download the file
-        let response = self.client.get(url).send().await?;
+        // Download the file; non-2xx responses are errors so an HTML error page is never cached
+        let response = self
+            .send_with_retry(|| self.client.get(url))
+            .await?
+            .error_for_status()?;
         let content = response.text().await?;
 
-        // Save to cache
+        // Validate before caching so a truncated or non-XML body never reaches the cache
+        if !looks_like_xml(&content) {
+            return Err(WeatherCheckerError::XmlParse(format!(
+                "Response for {} is not XML",
+                filename
+            )));
+        }
+        let parsed = self.parse_vpww54(&content)?;
+
+        // Save to cache atomically: write a temp file, then rename over the final name
         std::fs::create_dir_all(&self.config.data_dir)?;
-        std::fs::write(&file_path, &content)?;
+        // The temp name is unique so concurrent downloads of the same file cannot collide
+        let tmp_path = file_path.with_extension(format!("{:016x}.tmp", rand::random::<u64>()));
+        std::fs::write(&tmp_path, &content)?;
+        std::fs::rename(&tmp_path, &file_path)?;
+
+        db.record_vpww54_checksum(lmo, filename, &sha256_hex(content.as_bytes()))
+            .await?;
+
+        Ok(parsed)
+    }
+
+    /// Read, verify and parse a cached VPWW54 file
+    async fn read_cached_vpww54(
+        &self,
+        db: &Database,
+        file_path: &std::path::Path,
+        filename: &str,
+    ) -> Result<(Vec<WarningData>, DateTime<Utc>)> {
+        let content = std::fs::read_to_string(file_path)?;
+
+        if let Some(expected) = db.get_vpww54_checksum(filename).await? {
+            let actual = sha256_hex(content.as_bytes());
+            if actual != expected {
+                return Err(WeatherCheckerError::XmlParse(format!(
+                    "checksum mismatch (expected {}, got {})",
+                    expected, actual
+                )));
+            }
+        }
 
         self.parse_vpww54(&content)
     }
@@ -298,13 +553,17 @@ impl JMAFeed {
     /// Extracts warning information from the JMA VPWW54 format
     /// Returns (warnings, control_datetime) where control_datetime is from <Control><DateTime>
     fn parse_vpww54(&self, xml_content: &str) -> Result<(Vec<WarningData>, DateTime<Utc>)> {
+        Ok(Self::warning_data(Self::parse_vpww54_data(xml_content)?))
+    }
+
+    /// Parse a VPWW54 document into its Control, Head and per-city warnings
+    pub fn parse_vpww54_data(xml_content: &str) -> Result<VPWW54Data> {
         use quick_xml::events::Event;
         use quick_xml::Reader;
 
         let mut reader = Reader::from_str(xml_content);
         reader.config_mut().trim_text(true);
 
-        let mut vpww54_data: Option<VPWW54Data> = None;
         let mut control: Option<VPWW54Control> = None;
         let mut head: Option<VPWW54Head> = None;
         let mut warnings: Vec<CityWarning> = Vec::new();
@@ -332,7 +591,7 @@ impl JMAFeed {
                             in_control = true;
                             control = Some(VPWW54Control {
                                 title: String::new(),
-                                datetime: Utc::now(),
+                                datetime: clock::now(),
                                 status: String::new(),
                                 publishing_office: String::new(),
                             });
@@ -409,7 +668,7 @@ impl JMAFeed {
                         if head.is_none() {
                             head = Some(VPWW54Head {
                                 title: String::new(),
-                                report_datetime: Utc::now(),
+                                report_datetime: clock::now(),
                                 info_type: String::new(),
                                 info_kind: String::new(),
                             });
@@ -483,7 +742,7 @@ impl JMAFeed {
                 Ok(Event::Eof) => break,
                 Err(e) => {
                     tracing::error!("Error parsing VPWW54 XML: {:?}", e);
-                    return Err(crate::error::WeatherCheckerError::XmlParse(format!(
+                    return Err(WeatherCheckerError::XmlParse(format!(
                         "VPWW54 parse error: {}",
                         e
                     )));
@@ -494,50 +753,55 @@ impl JMAFeed {
         }
 
         // Build the complete VPWW54Data structure
-        if let (Some(ctrl), Some(hd)) = (control, head) {
-            vpww54_data = Some(VPWW54Data {
+        // A document without <Control> and <Head> is not a VPWW54 report (e.g. an error page)
+        match (control, head) {
+            (Some(ctrl), Some(hd)) => Ok(VPWW54Data {
                 control: ctrl,
                 head: hd,
                 warnings,
-            });
+            }),
+            _ => Err(WeatherCheckerError::XmlParse(
+                "VPWW54 document is missing <Control> or <Head>".into(),
+            )),
         }
+    }
 
+    /// Convert a parsed VPWW54 document to the legacy WarningData format
+    /// Returns (warnings, control_datetime) where control_datetime is from <Control><DateTime>
+    pub fn warning_data(data: VPWW54Data) -> (Vec<WarningData>, DateTime<Utc>) {
         // Convert to legacy WarningData format for backward compatibility
         let mut result = Vec::new();
-        let mut control_datetime = Utc::now(); // fallback
-        if let Some(data) = vpww54_data {
-            control_datetime = data.control.datetime;
-            for warning in data.warnings {
-                if warning.kinds.is_empty() {
-                    // No kinds means "発表警報・注意報はなし"
-                    result.push(WarningData {
-                        city: warning.area_name.clone(),
-                        warning_kind: String::new(),
-                        status: "発表警報・注意報はなし".to_string(),
-                    });
-                } else {
-                    for kind in warning.kinds {
-                        if let Some(kind_name) = kind.kind_name {
-                            result.push(WarningData {
-                                city: warning.area_name.clone(),
-                                warning_kind: kind_name,
-                                status: kind.status,
-                            });
-                        } else if kind.status == "発表警報・注意報はなし" {
-                            // Handle explicit "no warnings" status
-                            result.push(WarningData {
-                                city: warning.area_name.clone(),
-                                warning_kind: String::new(),
-                                status: kind.status,
-                            });
-                        }
+        let control_datetime = data.control.datetime;
+        for warning in data.warnings {
+            if warning.kinds.is_empty() {
+                // No kinds means "発表警報・注意報はなし"
+                result.push(WarningData {
+                    city: warning.area_name.clone(),
+                    warning_kind: String::new(),
+                    status: "発表警報・注意報はなし".to_string(),
+                });
+            } else {
+                for kind in warning.kinds {
+                    if let Some(kind_name) = kind.kind_name {
+                        result.push(WarningData {
+                            city: warning.area_name.clone(),
+                            warning_kind: kind_name,
+                            status: kind.status,
+                        });
+                    } else if kind.status == "発表警報・注意報はなし" {
+                        // Handle explicit "no warnings" status
+                        result.push(WarningData {
+                            city: warning.area_name.clone(),
+                            warning_kind: String::new(),
+                            status: kind.status,
+                        });
                     }
                 }
             }
         }
 
         tracing::debug!("Parsed {} warnings from VPWW54 XML", result.len());
-        Ok((result, control_datetime))
+        (result, control_datetime)
     }
 
     /// Get latest VPWW54 entry for a specific LMO (Local Meteorological Observatory)
@@ -547,6 +811,8 @@ impl JMAFeed {
     /// 3. Get the latest entry for the specified LMO
     /// 4. Download and parse the VPWW54 XML
     ///
+    /// Steps 2-4 are shared with push delivery via `latest_vpww54_in_feed`.
+    ///
     /// Returns: Option<(warnings, xml_filename, control_datetime)>
     pub async fn get_latest_vpww54_for_lmo(
         &self,
@@ -555,8 +821,23 @@ impl JMAFeed {
     ) -> Result<Option<(Vec<WarningData>, String, DateTime<Utc>)>> {
         tracing::info!("Fetching latest VPWW54 for LMO: {}", lmo);
 
-        // Step 1: Fetch extra.xml with conditional request
-        let xml_content = match self.fetch_extra_xml(db).await? {
+        // Step 1: Fetch extra.xml with conditional request (once per cycle)
+        let xml_content = self
+            .extra_xml
+            .get_or_try_init(|| self.load_extra_xml(db))
+            .await?;
+
+        let Some(xml_content) = xml_content else {
+            return Ok(None);
+        };
+
+        self.latest_vpww54_in_feed(lmo, xml_content, db).await
+    }
+
+    /// Fetch extra.xml, falling back to the cached copy on 304 Not Modified
+    /// The feed's `<updated>` is recorded either way, so a feed stuck on 304 shows up as stale.
+    async fn load_extra_xml(&self, db: &Database) -> Result<Option<Vec<u8>>> {
+        let content = match self.fetch_extra_xml(db).await? {
             Some(content) => content,
             None => {
                 // 304 Not Modified - read from cache
@@ -570,8 +851,24 @@ impl JMAFeed {
             }
         };
 
-        // Step 2: Parse extra.xml
-        let vpww_entries = self.parse_extra_xml(&xml_content).await?;
+        if let Some(updated) = feed_updated(&content) {
+            db.record_feed_updated(EXTRA_FEED, updated).await?;
+        }
+        Ok(Some(content))
+    }
+
+    /// Find the latest VPWW54 entry for an LMO in an Atom feed document, then download and parse it
+    /// Used for both the polled extra.xml and feeds pushed by a WebSub hub.
+    ///
+    /// Returns: Option<(warnings, xml_filename, control_datetime)>
+    pub async fn latest_vpww54_in_feed(
+        &self,
+        lmo: &str,
+        xml_content: &[u8],
+        db: &Database,
+    ) -> Result<Option<(Vec<WarningData>, String, DateTime<Utc>)>> {
+        // Step 2: Parse the feed
+        let vpww_entries = self.parse_extra_xml(xml_content).await?;
 
         // Step 3: Filter by LMO and get the latest entry
         let lmo_entries: Vec<_> = vpww_entries
@@ -595,7 +892,7 @@ impl JMAFeed {
 
         // Step 4: Download and parse VPWW54 XML
         let (warnings, control_datetime) = self
-            .fetch_vpww54(&latest_entry.url, &latest_entry.filename)
+            .fetch_vpww54(db, lmo, &latest_entry.url, &latest_entry.filename)
             .await?;
 
         tracing::info!(
@@ -608,3 +905,132 @@ impl JMAFeed {
         Ok(Some((warnings, latest_entry.filename.clone(), control_datetime)))
     }
 }
+
+/// Feed-level `<updated>` of an Atom feed (the one before the first `<entry>`)
+pub fn feed_updated(xml_content: &[u8]) -> Option<DateTime<Utc>> {
+    use quick_xml::events::Event;
+    use quick_xml::Reader;
+
+    let mut reader = Reader::from_reader(xml_content);
+    reader.config_mut().trim_text(true);
+    let mut in_updated = false;
+    let mut buf = Vec::new();
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) => match e.name().as_ref() {
+                b"entry" => return None,
+                b"updated" => in_updated = true,
+                _ => {}
+            },
+            Ok(Event::Text(e)) if in_updated => {
+                let text = e.unescape().ok()?;
+                return DateTime::parse_from_rfc3339(&text)
+                    .ok()
+                    .map(|dt| dt.with_timezone(&Utc));
+            }
+            Ok(Event::Eof) | Err(_) => return None,
+            _ => {}
+        }
+        buf.clear();
+    }
+}
+
+/// Whether an HTTP status is worth retrying (rate limiting or server-side failure)
+fn is_transient_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Cheap sanity check that a response body is an XML document rather than HTML or garbage
+fn looks_like_xml(content: &str) -> bool {
+    let trimmed = content.trim_start_matches('\u{feff}').trim_start();
+    trimmed.starts_with("<?xml") || trimmed.starts_with("<Report")
+}
+
+/// Hex-encoded SHA-256 digest, stored in `vpww54xml.checksum`
+fn sha256_hex(content: &[u8]) -> String {
+    hex::encode(Sha256::digest(content))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{http::StatusCode as HttpStatus, routing::get, Router};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const REPORT: &str =
+        include_str!("../tests/fixtures/vpww54/20250601010000_0_VPWW54_220000.xml");
+    const FILENAME: &str = "20250601010000_0_VPWW54_220000.xml";
+    const LMO: &str = "静岡地方気象台";
+
+    static REPORT_DOWNLOADS: AtomicUsize = AtomicUsize::new(0);
+
+    /// Serve the fixture report, an HTML page and a 404 from a local port
+    async fn serve() -> String {
+        let app = Router::new()
+            .route(
+                "/report.xml",
+                get(|| async {
+                    REPORT_DOWNLOADS.fetch_add(1, Ordering::SeqCst);
+                    REPORT
+                }),
+            )
+            .route(
+                "/html.xml",
+                get(|| async { "<html><body>Service Unavailable</body></html>" }),
+            )
+            .route(
+                "/missing.xml",
+                get(|| async { (HttpStatus::NOT_FOUND, "<?xml version=\"1.0\"?><Error/>") }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn fetch_vpww54_caches_only_valid_reports_and_verifies_the_cache() {
+        let base = serve().await;
+        let dir = std::env::temp_dir().join(format!("vpww54-{:016x}", rand::random::<u64>()));
+        let mut config = crate::config::test_config();
+        config.data_dir = dir.to_string_lossy().to_string();
+        config.http_max_retries = 0;
+        config.proxy_url = None;
+        std::fs::create_dir_all(&dir).unwrap();
+        let db = Database::connect(&dir.join("test.sqlite3").to_string_lossy())
+            .await
+            .unwrap();
+        db.init_schema().await.unwrap();
+        let feed = JMAFeed::new(config).unwrap();
+        let cached = dir.join(FILENAME);
+
+        // Error statuses and non-XML bodies are rejected and never cached
+        for path in ["/missing.xml", "/html.xml"] {
+            let url = format!("{}{}", base, path);
+            assert!(feed.fetch_vpww54(&db, LMO, &url, FILENAME).await.is_err());
+            assert!(!cached.exists(), "{} was cached", path);
+        }
+
+        let url = format!("{}/report.xml", base);
+        let (warnings, _) = feed.fetch_vpww54(&db, LMO, &url, FILENAME).await.unwrap();
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(REPORT_DOWNLOADS.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            db.get_vpww54_checksum(FILENAME).await.unwrap(),
+            Some(sha256_hex(REPORT.as_bytes()))
+        );
+
+        // A cache matching its checksum is reused
+        feed.fetch_vpww54(&db, LMO, &url, FILENAME).await.unwrap();
+        assert_eq!(REPORT_DOWNLOADS.load(Ordering::SeqCst), 1);
+
+        // A cache that no longer matches is discarded and downloaded again
+        std::fs::write(&cached, REPORT.replace("大雨注意報", "大雨警報")).unwrap();
+        feed.fetch_vpww54(&db, LMO, &url, FILENAME).await.unwrap();
+        assert_eq!(REPORT_DOWNLOADS.load(Ordering::SeqCst), 2);
+        assert_eq!(std::fs::read_to_string(&cached).unwrap(), REPORT);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,126 @@
+use crate::clock;
+use crate::config::Config;
+use crate::notification::EmailNotifier;
+use crate::scheduler;
+use chrono::{DateTime, FixedOffset, Utc};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Upper bound on the shutdown/crash notification, so a hung SMTP server cannot block exit
+const NOTIFY_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// State of the previous run, as recorded in the run-state file
+#[derive(Debug)]
+pub enum PreviousRun {
+    /// Stopped cleanly (signal or application exit)
+    Stopped { at: DateTime<Utc>, reason: String },
+    /// The scheduler crashed and the process exited with an error
+    Crashed { at: DateTime<Utc>, error: String },
+    /// Still marked running: killed, out of memory or power loss
+    Unfinished { started_at: DateTime<Utc> },
+}
+
+impl PreviousRun {
+    /// Line appended to the startup notification
+    pub fn describe(&self) -> String {
+        match self {
+            PreviousRun::Stopped { at, reason } => {
+                format!("Previous run stopped at {} ({})", jst(at), reason)
+            }
+            PreviousRun::Crashed { at, error } => {
+                format!("Previous run crashed at {}: {}", jst(at), error)
+            }
+            PreviousRun::Unfinished { started_at } => format!(
+                "Previous run (started {}) ended without recording an exit (killed or power loss)",
+                jst(started_at)
+            ),
+        }
+    }
+}
+
+/// Read how the previous run ended and mark this run as running
+/// Call once at startup; the state is overwritten by `notify_stopping`/`notify_crashed`.
+pub fn record_start() -> Option<PreviousRun> {
+    let previous = read_state();
+    write_state("running", clock::now(), "");
+    previous
+}
+
+/// Record a clean stop and send a "stopping" system notification (best-effort)
+pub async fn notify_stopping(reason: &str) {
+    tracing::info!("Stopping: {}", reason);
+    write_state("stopped", clock::now(), reason);
+    notify("stopping", format!("Service stopping ({})", reason)).await;
+}
+
+/// Record a crash and send a "crashed" system notification (best-effort)
+pub async fn notify_crashed(error: &str) {
+    write_state("crashed", clock::now(), error);
+    notify("crashed", format!("Service crashed: {}", error)).await;
+}
+
+/// Send a system notification, giving up after `NOTIFY_TIMEOUT`
+/// The notifier blocks on SMTP, so it runs on the blocking pool for the timeout to take
+/// effect without stalling a runtime worker. The blocking send itself ends within
+/// `SMTP_TIMEOUT_SECS` per SMTP operation, so it cannot hold up runtime shutdown for long.
+async fn notify(event: &'static str, details: String) {
+    let handle = tokio::runtime::Handle::current();
+    let send = tokio::task::spawn_blocking(move || {
+        let config = Config::from_env()?;
+        handle.block_on(EmailNotifier::new(config).send_system_notification(event, &details))
+    });
+
+    match tokio::time::timeout(NOTIFY_TIMEOUT, send).await {
+        Ok(Ok(Ok(()))) => {}
+        Ok(Ok(Err(e))) => tracing::warn!("Failed to send {} notification: {}", event, e),
+        Ok(Err(e)) => tracing::warn!("Failed to send {} notification: {}", event, e),
+        Err(_) => tracing::warn!(
+            "Timed out sending {} notification after {}s",
+            event,
+            NOTIFY_TIMEOUT.as_secs()
+        ),
+    }
+}
+
+/// Run-state file: state (`running`/`stopped`/`crashed`), UTC timestamp and reason, one per line
+fn state_path() -> PathBuf {
+    scheduler::heartbeat_path().with_file_name("run-state")
+}
+
+fn write_state(state: &str, at: DateTime<Utc>, reason: &str) {
+    let path = state_path();
+    if let Some(parent) = path.parent() {
+        if let Err(e) = std::fs::create_dir_all(parent) {
+            tracing::warn!("Failed to create run-state directory: {}", e);
+            return;
+        }
+    }
+    let content = format!("{}\n{}\n{}\n", state, at.to_rfc3339(), reason);
+    if let Err(e) = std::fs::write(&path, content) {
+        tracing::warn!("Failed to write run-state file: {}", e);
+    }
+}
+
+fn read_state() -> Option<PreviousRun> {
+    let content = std::fs::read_to_string(state_path()).ok()?;
+    let mut lines = content.lines();
+    let state = lines.next()?;
+    let at = DateTime::parse_from_rfc3339(lines.next()?)
+        .ok()?
+        .with_timezone(&Utc);
+    let reason = lines.collect::<Vec<_>>().join(" ");
+
+    match state {
+        "stopped" => Some(PreviousRun::Stopped { at, reason }),
+        "crashed" => Some(PreviousRun::Crashed { at, error: reason }),
+        "running" => Some(PreviousRun::Unfinished { started_at: at }),
+        _ => None,
+    }
+}
+
+fn jst(datetime: &DateTime<Utc>) -> String {
+    datetime
+        .with_timezone(&FixedOffset::east_opt(9 * 3600).unwrap())
+        .format("%Y/%m/%d %H:%M:%S JST")
+        .to_string()
+}
@@ -1,34 +1,77 @@
 // Prevents additional console window on Windows in release builds
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod ack;
 mod cleanup;
+mod clock;
 mod config;
 mod database;
+mod dispatcher;
 mod error;
+mod freshness;
 mod jma_feed;
+mod lifecycle;
 mod notification;
+mod reminder;
+mod replay;
 mod scheduler;
+mod severity;
+mod snapshot;
+mod warning_source;
 mod weather_checker;
+mod websub;
 
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    // Load environment variables FIRST
+fn main() -> anyhow::Result<()> {
+    // Load environment variables FIRST, before the runtime starts any threads
     dotenvy::dotenv().ok();
 
-    // Initialize logging
+    // `replay <dir>...` runs recorded XML through the checker instead of the service.
+    // It always runs dry, with its own scratch directory; set after .env is loaded so
+    // .env cannot turn it off.
+    let args: Vec<String> = std::env::args().collect();
+    let replay_dirs = (args.get(1).map(String::as_str) == Some("replay")).then(|| &args[2..]);
+    if replay_dirs.is_some() {
+        std::env::set_var("DRY_RUN", "true");
+        let replay_dir = std::env::var("REPLAY_DIR").unwrap_or_else(|_| "data/replay".into());
+        std::env::set_var("DRY_RUN_DIR", replay_dir);
+    }
+
+    // Initialize logging; replay prints notifications on stdout, so its logs go to stderr
+    let (default_filter, writer) = match replay_dirs {
+        Some(_) => (
+            "tauri_weather_checker=warn",
+            BoxMakeWriter::new(std::io::stderr),
+        ),
+        None => (
+            "tauri_weather_checker=info",
+            BoxMakeWriter::new(std::io::stdout),
+        ),
+    };
     tracing_subscriber::registry()
         .with(
             tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "tauri_weather_checker=info".into()),
+                .unwrap_or_else(|_| default_filter.into()),
         )
-        .with(tracing_subscriber::fmt::layer())
+        .with(tracing_subscriber::fmt::layer().with_writer(writer))
         .init();
 
+    let runtime = tokio::runtime::Runtime::new()?;
+    match replay_dirs {
+        Some(dirs) => runtime.block_on(replay::run(dirs))?,
+        None => runtime.block_on(run_service())?,
+    }
+    Ok(())
+}
+
+/// Run the scheduler and the Tauri application until shutdown
+async fn run_service() -> anyhow::Result<()> {
     tracing::info!("Starting Weather Checker...");
 
-    // Initialize database
+    // Initialize database (a fresh scratch copy of the live one in dry-run mode)
+    database::Database::seed_scratch().await?;
     let db = database::Database::new().await?;
     db.init_schema().await?;
     tracing::info!("Database initialized");
@@ -45,8 +88,9 @@ async fn main() -> anyhow::Result<()> {
     // Spawn shutdown signal handler
     let shutdown_token = cancel_token.clone();
     tokio::spawn(async move {
-        wait_for_shutdown_signal().await;
+        let signal = wait_for_shutdown_signal().await;
         tracing::info!("Shutdown signal received, stopping gracefully...");
+        lifecycle::notify_stopping(&format!("received {}", signal)).await;
         shutdown_token.cancel();
     });
 
@@ -62,6 +106,7 @@ async fn main() -> anyhow::Result<()> {
                 // Scheduler returned an error
                 if !monitor_token.is_cancelled() {
                     tracing::error!("Scheduler crashed with error: {}", e);
+                    lifecycle::notify_crashed(&e.to_string()).await;
                     std::process::exit(1);
                 }
             }
@@ -69,6 +114,7 @@ async fn main() -> anyhow::Result<()> {
                 // Scheduler task panicked
                 if !monitor_token.is_cancelled() {
                     tracing::error!("Scheduler task panicked: {}", e);
+                    lifecycle::notify_crashed(&format!("scheduler task panicked: {}", e)).await;
                     std::process::exit(1);
                 }
             }
@@ -88,6 +134,9 @@ async fn main() -> anyhow::Result<()> {
         .expect("error while running tauri application");
 
     // Tauri has exited, ensure scheduler is cancelled
+    if !cancel_token.is_cancelled() {
+        lifecycle::notify_stopping("application exited").await;
+    }
     cancel_token.cancel();
     // Give scheduler a moment to clean up
     tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
@@ -96,16 +145,19 @@ async fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
-async fn wait_for_shutdown_signal() {
+/// Wait for a termination signal and return its name
+async fn wait_for_shutdown_signal() -> &'static str {
     #[cfg(unix)]
     {
         use tokio::signal::unix::{signal, SignalKind};
         let mut sigterm = signal(SignalKind::terminate()).expect("failed to install SIGTERM handler");
         let mut sigint = signal(SignalKind::interrupt()).expect("failed to install SIGINT handler");
-        tokio::select! {
-            _ = sigterm.recv() => tracing::info!("Received SIGTERM"),
-            _ = sigint.recv() => tracing::info!("Received SIGINT"),
-        }
+        let name = tokio::select! {
+            _ = sigterm.recv() => "SIGTERM",
+            _ = sigint.recv() => "SIGINT",
+        };
+        tracing::info!("Received {}", name);
+        name
     }
     #[cfg(not(unix))]
     {
@@ -113,5 +165,6 @@ async fn wait_for_shutdown_signal() {
             .await
             .expect("failed to install Ctrl+C handler");
         tracing::info!("Received Ctrl+C");
+        "Ctrl+C"
     }
 }
@@ -1,118 +1,759 @@
-use crate::config::Config;
-use crate::error::Result;
+use crate::clock;
+use crate::config::{AlertSmtpConfig, Config, RecipientGroup, SmtpTls};
+use crate::database::is_active_status;
+use crate::error::{Result, WeatherCheckerError};
+use crate::severity::{self, Severity, SeverityChange};
 use chrono::{DateTime, FixedOffset, Utc};
-use lettre::message::header::ContentType;
+use lettre::message::header::{ContentType, HeaderName, HeaderValue};
+use lettre::message::MessageBuilder;
 use lettre::transport::smtp::authentication::Credentials;
+use lettre::transport::smtp::client::{Certificate, Tls, TlsParameters};
 use lettre::{Message, SmtpTransport, Transport};
+use serde::{Deserialize, Serialize};
 use std::env;
 
-pub struct EmailNotifier {
-    config: Config,
+/// Outbox message type for a single warning status change (older outbox rows only)
+pub const WARNING_MESSAGE_TYPE: &str = "warning";
+/// Outbox message type for all changes to one city in one report
+pub const CITY_MESSAGE_TYPE: &str = "city_warning";
+/// Outbox message type for a reminder that a warning is still in effect
+pub const REMINDER_MESSAGE_TYPE: &str = "reminder";
+
+/// Payload of the single-change `warning` message type
+/// New notifications use [`CityNotification`]; this is still accepted from older outbox rows.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WarningNotification {
+    pub lmo: String,
+    pub city: String,
+    pub warning_kind: String,
+    pub status: String,
+    pub jma_url: Option<String>,
+    pub control_datetime: DateTime<Utc>,
+    /// Extra remark appended to the body as a NOTE line
+    #[serde(default)]
+    pub note: Option<String>,
+    /// Kind of the same phenomenon that ended with this change (severity transition)
+    #[serde(default)]
+    pub previous_kind: Option<String>,
+    #[serde(default)]
+    pub severity_change: Option<SeverityChange>,
 }
 
-const DEFAULT_URL: &str = "https://www.jma.go.jp/bosai/warning/#lang=ja";
+/// Payload of a queued notification: every change to one city in one report
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CityNotification {
+    pub lmo: String,
+    pub city: String,
+    pub jma_url: Option<String>,
+    pub control_datetime: DateTime<Utc>,
+    /// Changes in this report, most severe first
+    pub changes: Vec<WarningChange>,
+    /// Every kind in effect for the city after the report: (kind, status)
+    #[serde(default)]
+    pub active: Vec<(String, String)>,
+}
 
-impl EmailNotifier {
-    pub fn new(config: Config) -> Self {
-        Self { config }
+/// One kind's status change within a [`CityNotification`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WarningChange {
+    pub warning_kind: String,
+    /// Status before the report (None when the kind was not in effect)
+    pub previous_status: Option<String>,
+    pub status: String,
+    /// Extra remark appended to the section as a NOTE line
+    pub note: Option<String>,
+    /// Kind of the same phenomenon that ended with this change (severity transition)
+    pub previous_kind: Option<String>,
+    pub severity_change: Option<SeverityChange>,
+}
+
+impl From<WarningNotification> for CityNotification {
+    fn from(n: WarningNotification) -> Self {
+        CityNotification {
+            lmo: n.lmo,
+            city: n.city,
+            jma_url: n.jma_url,
+            control_datetime: n.control_datetime,
+            changes: vec![WarningChange {
+                warning_kind: n.warning_kind,
+                previous_status: None,
+                status: n.status,
+                note: n.note,
+                previous_kind: n.previous_kind,
+                severity_change: n.severity_change,
+            }],
+            active: Vec::new(),
+        }
     }
+}
 
-    pub async fn send_warning_notification(
-        &self,
-        city: &str,
-        warning_kind: &str,
-        status: &str,
-        lmo: &str,
-        jma_url: Option<&str>,
-        control_datetime: &DateTime<Utc>,
-    ) -> Result<()> {
-        // Subject format: {city}:{warning}:{status}
-        // Add "test:" prefix when RUST_LOG contains "debug"
-        let base_subject = format!("{}:{}:{}", city, warning_kind, status);
-        let subject = if env::var("RUST_LOG")
-            .map(|v| v.contains("debug"))
-            .unwrap_or(false)
-        {
-            format!("test:{}", base_subject)
-        } else {
-            base_subject
-        };
+impl WarningChange {
+    /// "upgraded to 警報" / "downgraded to 注意報" for a severity transition
+    fn change_description(&self) -> Option<String> {
+        let change = self.severity_change?;
+        let to = severity::classify(&self.warning_kind)?.1;
+        Some(change.describe(to))
+    }
+
+    /// Highest severity involved, counting the ended kind of a transition
+    fn severity(&self) -> Option<Severity> {
+        let current = severity::classify(&self.warning_kind).map(|(_, s)| s);
+        let previous = self
+            .previous_kind
+            .as_deref()
+            .and_then(severity::classify)
+            .map(|(_, s)| s);
+        current.max(previous)
+    }
+
+    /// Optional PREV / CHNG / NOTE lines shared by both body formats
+    fn extra_lines(&self) -> String {
+        let mut lines = String::new();
+        if let (Some(previous), Some(change)) = (&self.previous_kind, self.change_description()) {
+            lines.push_str(&format!("PREV:{}\nCHNG:{}\n", previous, change));
+        }
+        if let Some(note) = &self.note {
+            lines.push_str(&format!("NOTE:{}\n", note));
+        }
+        lines
+    }
+}
+
+impl CityNotification {
+    /// Highest severity among the changes
+    pub fn severity(&self) -> Option<Severity> {
+        self.changes
+            .iter()
+            .filter_map(WarningChange::severity)
+            .max()
+    }
 
+    /// Subject format: {city}:{warning}:{status}
+    /// A severity transition uses the change instead: {city}:{warning}:upgraded to 警報
+    /// Several changes: {city}:{first warning}ほか{n}件
+    fn subject(&self) -> String {
+        match self.changes.as_slice() {
+            [change] => {
+                let description = change.change_description();
+                format!(
+                    "{}:{}:{}",
+                    self.city,
+                    change.warning_kind,
+                    description.as_deref().unwrap_or(&change.status)
+                )
+            }
+            changes => format!(
+                "{}:{}ほか{}件",
+                self.city,
+                changes
+                    .first()
+                    .map(|c| c.warning_kind.as_str())
+                    .unwrap_or(""),
+                changes.len().saturating_sub(1)
+            ),
+        }
+    }
+
+    /// Body format matching Python implementation, for a single change:
+    /// LWO:{obs}
+    /// DATE:{dts}
+    /// CITY:{city}
+    /// WARN:{warning}
+    /// STAT:{status}
+    /// LINK:気象庁｜{city名}の警報・注意報
+    /// URL:{url}
+    /// PREV:{kind}   (only for a severity transition: the kind that ended)
+    /// CHNG:{change} (only for a severity transition)
+    /// NOTE:{note}   (only when present, e.g. implicit cancellation)
+    /// END
+    ///
+    /// Several changes use the repeated-section variant: the header lines, then
+    /// `COUNT:{n}` and one `ITEM` section per change (WARN / FROM / STAT and the
+    /// optional lines above), then one `ACTIVE:{warning}:{status}` line per kind in
+    /// effect (`ACTIVE:なし` when none), then END.
+    fn body(&self) -> String {
         // Convert control datetime (UTC) to JST for display, matching Python implementation
         let jst = FixedOffset::east_opt(9 * 3600).unwrap();
-        let jst_datetime = control_datetime.with_timezone(&jst);
+        let jst_datetime = self.control_datetime.with_timezone(&jst);
         let timestamp = jst_datetime.format("%Y/%m/%d %H:%M:%S").to_string();
 
         // Get JMA URL for the city (use config URL or fall back to default)
+        let jma_url = self.jma_url.as_deref();
         let resolved_url = jma_url.unwrap_or(DEFAULT_URL);
-        let city_name = if jma_url.is_some() { city } else { "全国" };
-
-        // Body format matching Python implementation:
-        // LWO:{obs}
-        // DATE:{dts}
-        // CITY:{city}
-        // WARN:{warning}
-        // STAT:{status}
-        // LINK:気象庁｜{city名}の警報・注意報
-        // URL:{url}
-        // END
-        let body = format!(
-            "LWO:{}\nDATE:{}\nCITY:{}\nWARN:{}\nSTAT:{}\nLINK:気象庁｜{}の警報・注意報\nURL:{}\nEND",
-            lmo, timestamp, city, warning_kind, status, city_name, resolved_url
+        let city_name = if jma_url.is_some() {
+            &self.city
+        } else {
+            "全国"
+        };
+
+        if let [change] = self.changes.as_slice() {
+            return format!(
+                "LWO:{}\nDATE:{}\nCITY:{}\nWARN:{}\nSTAT:{}\nLINK:気象庁｜{}の警報・注意報\nURL:{}\n{}END",
+                self.lmo,
+                timestamp,
+                self.city,
+                change.warning_kind,
+                change.status,
+                city_name,
+                resolved_url,
+                change.extra_lines()
+            );
+        }
+
+        let mut body = format!(
+            "LWO:{}\nDATE:{}\nCITY:{}\nLINK:気象庁｜{}の警報・注意報\nURL:{}\nCOUNT:{}\n",
+            self.lmo,
+            timestamp,
+            self.city,
+            city_name,
+            resolved_url,
+            self.changes.len()
         );
+        for change in &self.changes {
+            body.push_str(&format!(
+                "ITEM\nWARN:{}\nFROM:{}\nSTAT:{}\n{}",
+                change.warning_kind,
+                change.previous_status.as_deref().unwrap_or("-"),
+                change.status,
+                change.extra_lines()
+            ));
+        }
+        if self.active.is_empty() {
+            body.push_str("ACTIVE:なし\n");
+        }
+        for (kind, status) in &self.active {
+            body.push_str(&format!("ACTIVE:{}:{}\n", kind, status));
+        }
+        body.push_str("END");
+        body
+    }
+}
 
-        let mut email_builder = Message::builder()
-            .from(self.config.gmail_from.parse()?)
-            .to(self.config.email_to.parse()?)
-            .subject(subject);
+/// Payload of a reminder that a kind is still in effect for a city
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReminderNotification {
+    pub lmo: String,
+    pub city: String,
+    pub warning_kind: String,
+    pub status: String,
+    pub jma_url: Option<String>,
+    /// Start of the current active period
+    pub active_since: DateTime<Utc>,
+    /// Time of the scan that queued the reminder
+    pub checked_at: DateTime<Utc>,
+    /// Whether a notification about the current active period was already acknowledged
+    #[serde(default)]
+    pub acknowledged: bool,
+}
 
-        if let Some(bcc) = &self.config.email_bcc {
-            email_builder = email_builder.bcc(bcc.parse()?);
+impl ReminderNotification {
+    /// Subject format: {city}:{warning}:still in effect since {MM/DD HH:MM}
+    fn subject(&self) -> String {
+        format!(
+            "{}:{}:still in effect since {}",
+            self.city,
+            self.warning_kind,
+            to_jst(&self.active_since).format("%m/%d %H:%M")
+        )
+    }
+
+    /// Body format: the warning body with a SINCE line (JST) and no change lines
+    /// LWO / DATE / CITY / WARN / STAT / SINCE / LINK / URL / END
+    fn body(&self) -> String {
+        let jma_url = self.jma_url.as_deref();
+        let city_name = if jma_url.is_some() {
+            &self.city
+        } else {
+            "全国"
+        };
+        format!(
+            "LWO:{}\nDATE:{}\nCITY:{}\nWARN:{}\nSTAT:{}\nSINCE:{}\nLINK:気象庁｜{}の警報・注意報\nURL:{}\nEND",
+            self.lmo,
+            to_jst(&self.checked_at).format("%Y/%m/%d %H:%M:%S"),
+            self.city,
+            self.warning_kind,
+            self.status,
+            to_jst(&self.active_since).format("%Y/%m/%d %H:%M:%S"),
+            city_name,
+            jma_url.unwrap_or(DEFAULT_URL)
+        )
+    }
+}
+
+/// Everything in effect for the monitored cities, sent once after a restart
+#[derive(Debug, Clone)]
+pub struct StartupSnapshot {
+    pub taken_at: DateTime<Utc>,
+    /// Last heartbeat of the previous run; entries that came into effect later are new
+    pub last_seen: Option<DateTime<Utc>>,
+    pub cities: Vec<CitySnapshot>,
+}
+
+/// Active warnings of one monitored city
+#[derive(Debug, Clone)]
+pub struct CitySnapshot {
+    pub lmo: String,
+    pub city: String,
+    pub entries: Vec<SnapshotEntry>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SnapshotEntry {
+    pub warning_kind: String,
+    pub status: String,
+    pub active_since: Option<DateTime<Utc>>,
+    /// Came into effect after the previous run was last seen alive
+    pub is_new: bool,
+}
+
+impl StartupSnapshot {
+    fn count(&self) -> (usize, usize) {
+        let entries = self.cities.iter().flat_map(|c| &c.entries);
+        let new = entries.clone().filter(|e| e.is_new).count();
+        (entries.count(), new)
+    }
+
+    /// Subject format: snapshot:{n}件発表中(新規{m}件)
+    fn subject(&self) -> String {
+        let (total, new) = self.count();
+        format!("snapshot:{}件発表中(新規{}件)", total, new)
+    }
+
+    /// Body format: DATE / LAST, then per city LWO / CITY and one
+    /// `WARN:{kind}:{status}:{since}` line per active kind (prefixed `NEW:` when new
+    /// since the last shutdown, `WARN:なし` when nothing is in effect), then END
+    fn body(&self) -> String {
+        let format_time = |t: &DateTime<Utc>| to_jst(t).format("%Y/%m/%d %H:%M:%S").to_string();
+        let mut body = format!(
+            "DATE:{}\nLAST:{}\n",
+            format_time(&self.taken_at),
+            self.last_seen.as_ref().map_or("-".to_string(), format_time)
+        );
+        for city in &self.cities {
+            body.push_str(&format!("LWO:{}\nCITY:{}\n", city.lmo, city.city));
+            if city.entries.is_empty() {
+                body.push_str("WARN:なし\n");
+            }
+            for entry in &city.entries {
+                body.push_str(&format!(
+                    "{}WARN:{}:{}:{}\n",
+                    if entry.is_new { "NEW:" } else { "" },
+                    entry.warning_kind,
+                    entry.status,
+                    entry
+                        .active_since
+                        .as_ref()
+                        .map_or("-".to_string(), format_time)
+                ));
+            }
         }
+        body.push_str("END");
+        body
+    }
+}
+
+/// A decoded outbox notification for warning recipients
+#[derive(Debug, Clone)]
+pub enum Notification {
+    City(CityNotification),
+    Reminder(ReminderNotification),
+}
+
+impl Notification {
+    /// Decode an outbox payload; `None` for message types that are not warning notifications
+    pub fn decode(message_type: &str, payload: &str) -> Option<serde_json::Result<Self>> {
+        let decoded = match message_type {
+            WARNING_MESSAGE_TYPE => serde_json::from_str::<WarningNotification>(payload)
+                .map(|n| Notification::City(n.into())),
+            CITY_MESSAGE_TYPE => serde_json::from_str(payload).map(Notification::City),
+            REMINDER_MESSAGE_TYPE => serde_json::from_str(payload).map(Notification::Reminder),
+            _ => return None,
+        };
+        Some(decoded)
+    }
+
+    /// Severity used for quiet-hours holding
+    pub fn severity(&self) -> Option<Severity> {
+        match self {
+            Notification::City(n) => n.severity(),
+            Notification::Reminder(n) => severity::classify(&n.warning_kind).map(|(_, s)| s),
+        }
+    }
+
+    /// Whether a 特別警報 is in effect in this notification (issued, continued or reminded)
+    /// Cancelling one is not an emergency and takes the regular path.
+    pub fn is_emergency(&self) -> bool {
+        self.in_effect_at_least(Severity::Emergency)
+    }
+
+    /// Whether the notification gets an acknowledgement link
+    /// Only a 警報 or 特別警報 in effect needs one; cancellations do not, and neither do
+    /// reminders for a period someone already acknowledged.
+    pub fn needs_ack(&self) -> bool {
+        match self {
+            Notification::Reminder(n) if n.acknowledged => false,
+            _ => self.in_effect_at_least(Severity::Warning),
+        }
+    }
 
-        let email = email_builder.header(ContentType::TEXT_PLAIN).body(body)?;
+    /// Whether a kind of at least `min` severity is in effect in this notification
+    fn in_effect_at_least(&self, min: Severity) -> bool {
+        let in_effect = |kind: &str, status: &str| {
+            severity::classify(kind).is_some_and(|(_, s)| s >= min) && is_active_status(status)
+        };
+        match self {
+            Notification::City(n) => n
+                .changes
+                .iter()
+                .any(|c| in_effect(&c.warning_kind, &c.status)),
+            Notification::Reminder(n) => in_effect(&n.warning_kind, &n.status),
+        }
+    }
+
+    /// Subject line, prefixed with "emergency:" while a 特別警報 is in effect
+    pub fn subject(&self) -> String {
+        let subject = match self {
+            Notification::City(n) => n.subject(),
+            Notification::Reminder(n) => n.subject(),
+        };
+        if self.is_emergency() {
+            format!("emergency:{}", subject)
+        } else {
+            subject
+        }
+    }
+
+    pub fn body(&self) -> String {
+        match self {
+            Notification::City(n) => n.body(),
+            Notification::Reminder(n) => n.body(),
+        }
+    }
+
+    /// Body with an `ACK:{url}` line before the closing END when an ack link is given
+    fn body_with_ack(&self, ack_url: Option<&str>) -> String {
+        let body = self.body();
+        match ack_url {
+            Some(url) => format!(
+                "{}ACK:{}\nEND",
+                body.strip_suffix("END").unwrap_or(&body),
+                url
+            ),
+            None => body,
+        }
+    }
+
+    fn city(&self) -> &str {
+        match self {
+            Notification::City(n) => &n.city,
+            Notification::Reminder(n) => &n.city,
+        }
+    }
+}
+
+fn to_jst(datetime: &DateTime<Utc>) -> DateTime<FixedOffset> {
+    datetime.with_timezone(&FixedOffset::east_opt(9 * 3600).unwrap())
+}
+
+/// Body of system notifications and alerts: EVENT / DATE (JST) / DETAILS / END
+fn system_body(event: &str, details: &str) -> String {
+    format!(
+        "EVENT:{}\nDATE:{}\nDETAILS:{}\nEND",
+        event,
+        to_jst(&clock::now()).format("%Y/%m/%d %H:%M:%S"),
+        details
+    )
+}
+
+/// Every certificate of a PEM file, which may be a CA bundle
+/// lettre's `Certificate::from_pem` reads only the first one, so the blocks are split here
+/// to trust the same CAs as the JMA client's `from_pem_bundle`.
+fn pem_certificates(pem: &[u8]) -> Result<Vec<Certificate>> {
+    const END: &str = "-----END CERTIFICATE-----";
+
+    let text = String::from_utf8_lossy(pem);
+    let mut certs = Vec::new();
+    let mut rest = text.as_ref();
+    while let Some(start) = rest.find("-----BEGIN CERTIFICATE-----") {
+        let Some(end) = rest[start..].find(END).map(|e| start + e + END.len()) else {
+            break;
+        };
+        certs.push(Certificate::from_pem(&rest.as_bytes()[start..end])?);
+        rest = &rest[end..];
+    }
 
+    if certs.is_empty() {
+        return Err(WeatherCheckerError::Config(
+            "No certificate found in CA certificate file".into(),
+        ));
+    }
+    Ok(certs)
+}
+
+/// Mark the message high priority for mail clients when a 特別警報 is in effect
+fn with_priority(builder: MessageBuilder, notification: &Notification) -> MessageBuilder {
+    if !notification.is_emergency() {
+        return builder;
+    }
+    [
+        ("X-Priority", "1 (Highest)"),
+        ("Importance", "high"),
+        ("Priority", "urgent"),
+    ]
+    .into_iter()
+    .fold(builder, |builder, (name, value)| {
+        builder.raw_header(HeaderValue::new(
+            HeaderName::new_from_ascii_str(name),
+            value.to_string(),
+        ))
+    })
+}
+
+/// Add "test:" prefix when RUST_LOG contains "debug"
+fn with_test_prefix(subject: String) -> String {
+    if env::var("RUST_LOG")
+        .map(|v| v.contains("debug"))
+        .unwrap_or(false)
+    {
+        format!("test:{}", subject)
+    } else {
+        subject
+    }
+}
+
+pub struct EmailNotifier {
+    config: Config,
+}
+
+const DEFAULT_URL: &str = "https://www.jma.go.jp/bosai/warning/#lang=ja";
+pub const SMTP_RELAY: &str = "smtp.gmail.com";
+
+impl EmailNotifier {
+    pub fn new(config: Config) -> Self {
+        Self { config }
+    }
+
+    /// Build the SMTP transport, trusting any extra CA certificates from the config
+    /// lettre has no proxy support; `Config` refuses a `PROXY_URL` that does not exempt
+    /// the SMTP hosts through `NO_PROXY`.
+    fn mailer(&self) -> Result<SmtpTransport> {
         let creds = Credentials::new(
             self.config.gmail_from.clone(),
             self.config.gmail_app_pass.clone(),
         );
 
-        let mailer = SmtpTransport::relay("smtp.gmail.com")?
+        let mut builder = SmtpTransport::relay(SMTP_RELAY)?
             .credentials(creds)
-            .build();
+            .timeout(Some(self.smtp_timeout()));
 
-        mailer.send(&email)?;
+        let pems = self.config.load_extra_ca_pems()?;
+        if !pems.is_empty() {
+            let mut tls = TlsParameters::builder(SMTP_RELAY.to_string());
+            for pem in pems {
+                for cert in pem_certificates(&pem)? {
+                    tls = tls.add_root_certificate(cert);
+                }
+            }
+            builder = builder.tls(Tls::Wrapper(tls.build()?));
+        }
+
+        Ok(builder.build())
+    }
+
+    pub async fn send_warning_notification(
+        &self,
+        notification: &Notification,
+        group: &RecipientGroup,
+        ack_url: Option<&str>,
+    ) -> Result<()> {
+        let body = notification.body_with_ack(ack_url);
+        let email = with_priority(self.message_to(group)?, notification)
+            .subject(with_test_prefix(notification.subject()))
+            .header(ContentType::TEXT_PLAIN)
+            .body(body.clone())?;
+
+        self.send(&email, &body)?;
 
         tracing::info!(
-            "Sent notification for {} - {} ({})",
-            city,
-            warning_kind,
-            status
+            "Sent notification for {} to {}: {}",
+            notification.city(),
+            group.name,
+            notification.subject()
         );
 
         Ok(())
     }
 
-    pub async fn send_system_notification(&self, event: &str, details: &str) -> Result<()> {
-        let base_subject = format!("weather-checker: {}", event);
-        let subject = if env::var("RUST_LOG")
-            .map(|v| v.contains("debug"))
-            .unwrap_or(false)
-        {
-            format!("test:{}", base_subject)
-        } else {
-            base_subject
-        };
+    /// Re-send an unacknowledged notification to the escalation recipients
+    pub async fn send_escalation(
+        &self,
+        notification: &Notification,
+        group: &RecipientGroup,
+        ack_url: &str,
+        minutes: i64,
+    ) -> Result<()> {
+        let subject = format!("escalation:{}", notification.subject());
+        let body = format!(
+            "ESCALATION:{}分以内に確認されませんでした\n{}",
+            minutes,
+            notification.body_with_ack(Some(ack_url))
+        );
 
-        let jst = FixedOffset::east_opt(9 * 3600).unwrap();
-        let jst_now = Utc::now().with_timezone(&jst);
-        let timestamp = jst_now.format("%Y/%m/%d %H:%M:%S").to_string();
+        let email = with_priority(self.message_to(group)?, notification)
+            .subject(with_test_prefix(subject))
+            .header(ContentType::TEXT_PLAIN)
+            .body(body.clone())?;
 
-        let body = format!(
-            "EVENT:{}\nDATE:{}\nDETAILS:{}\nEND",
-            event, timestamp, details
+        self.send(&email, &body)?;
+
+        tracing::info!(
+            "Sent escalation to {}: {}",
+            group.name,
+            notification.subject()
+        );
+
+        Ok(())
+    }
+
+    /// Send notifications held during quiet hours as one digest email
+    /// The body repeats the regular LWO...END section for each notification, oldest first.
+    pub async fn send_warning_digest(
+        &self,
+        notifications: &[Notification],
+        group: &RecipientGroup,
+    ) -> Result<()> {
+        let subject = format!("digest:{}件の警報・注意報の更新", notifications.len());
+        let body = notifications
+            .iter()
+            .map(Notification::body)
+            .collect::<Vec<_>>()
+            .join("\n\n");
+
+        let email = self
+            .message_to(group)?
+            .subject(with_test_prefix(subject))
+            .header(ContentType::TEXT_PLAIN)
+            .body(body.clone())?;
+
+        self.send(&email, &body)?;
+
+        tracing::info!(
+            "Sent digest of {} notifications to {}",
+            notifications.len(),
+            group.name
+        );
+
+        Ok(())
+    }
+
+    /// Send the post-restart snapshot of active warnings to a recipient group
+    pub async fn send_startup_snapshot(
+        &self,
+        snapshot: &StartupSnapshot,
+        group: &RecipientGroup,
+    ) -> Result<()> {
+        let body = snapshot.body();
+        let email = self
+            .message_to(group)?
+            .subject(with_test_prefix(snapshot.subject()))
+            .header(ContentType::TEXT_PLAIN)
+            .body(body.clone())?;
+
+        self.send(&email, &body)?;
+
+        tracing::info!(
+            "Sent startup snapshot to {}: {}",
+            group.name,
+            snapshot.subject()
         );
 
+        Ok(())
+    }
+
+    /// SMTP transport for the alert server, independent of the Gmail account
+    fn alert_mailer(&self, smtp: &AlertSmtpConfig) -> Result<SmtpTransport> {
+        let mut builder = match smtp.tls {
+            SmtpTls::StartTls => SmtpTransport::starttls_relay(&smtp.host)?,
+            SmtpTls::Wrapper => SmtpTransport::relay(&smtp.host)?,
+            SmtpTls::None => SmtpTransport::builder_dangerous(&smtp.host),
+        }
+        .port(smtp.port)
+        .timeout(Some(self.smtp_timeout()));
+
+        if let (Some(username), Some(password)) = (&smtp.username, &smtp.password) {
+            builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+
+        let pems = self.config.load_extra_ca_pems()?;
+        if !pems.is_empty() && smtp.tls != SmtpTls::None {
+            let mut tls = TlsParameters::builder(smtp.host.clone());
+            for pem in pems {
+                for cert in pem_certificates(&pem)? {
+                    tls = tls.add_root_certificate(cert);
+                }
+            }
+            let tls = tls.build()?;
+            builder = builder.tls(match smtp.tls {
+                SmtpTls::Wrapper => Tls::Wrapper(tls),
+                _ => Tls::Required(tls),
+            });
+        }
+
+        Ok(builder.build())
+    }
+
+    /// Bound on each blocking SMTP operation, so a hung server cannot stall the caller
+    fn smtp_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.config.smtp_timeout_secs)
+    }
+
+    /// Send an email, or only log it in dry-run mode
+    /// `body` is the unencoded body of `email`, logged verbatim in dry-run mode.
+    fn send(&self, email: &Message, body: &str) -> Result<()> {
+        self.send_through(None, email, body)
+    }
+
+    /// Send an email through the alert SMTP server if given, otherwise the Gmail account
+    fn send_through(
+        &self,
+        alert_smtp: Option<&AlertSmtpConfig>,
+        email: &Message,
+        body: &str,
+    ) -> Result<()> {
+        if self.config.dry_run {
+            tracing::info!(
+                "Dry run, not sending to {:?}\nSubject: {}\n{}",
+                email.envelope().to(),
+                email.headers().get_raw("Subject").unwrap_or_default(),
+                body
+            );
+            return Ok(());
+        }
+
+        let mailer = match alert_smtp {
+            Some(smtp) => self.alert_mailer(smtp)?,
+            None => self.mailer()?,
+        };
+        mailer.send(email)?;
+        Ok(())
+    }
+
+    /// Start a message from the configured sender to a recipient group
+    fn message_to(&self, group: &RecipientGroup) -> Result<MessageBuilder> {
+        let mut builder = Message::builder().from(self.config.gmail_from.parse()?);
+        for to in &group.to {
+            builder = builder.to(to.parse()?);
+        }
+        for bcc in &group.bcc {
+            builder = builder.bcc(bcc.parse()?);
+        }
+        Ok(builder)
+    }
+
+    pub async fn send_system_notification(&self, event: &str, details: &str) -> Result<()> {
+        let subject = with_test_prefix(format!("weather-checker: {}", event));
+        let body = system_body(event, details);
+
         let mut email_builder = Message::builder()
             .from(self.config.gmail_from.parse()?)
             .to(self.config.email_to.parse()?)
@@ -122,22 +763,43 @@ impl EmailNotifier {
             email_builder = email_builder.bcc(bcc.parse()?);
         }
 
-        let email = email_builder.header(ContentType::TEXT_PLAIN).body(body)?;
-
-        let creds = Credentials::new(
-            self.config.gmail_from.clone(),
-            self.config.gmail_app_pass.clone(),
-        );
-
-        let mailer = SmtpTransport::relay("smtp.gmail.com")?
-            .credentials(creds)
-            .build();
+        let email = email_builder
+            .header(ContentType::TEXT_PLAIN)
+            .body(body.clone())?;
 
-        mailer.send(&email)?;
+        self.send(&email, &body)?;
 
         tracing::info!("Sent system notification: {}", event);
 
         Ok(())
     }
 
+    /// Send an operational alert, such as checks failing or recovering
+    /// Uses the alert SMTP server when `ALERT_SMTP_HOST` is set, so alerts still go out when
+    /// the Gmail account is what is failing. Recipients are `ALERT_TO`, or `EMAIL_TO`.
+    pub async fn send_alert(&self, event: &str, details: &str) -> Result<()> {
+        let alert_smtp = self.config.alert_smtp.as_ref();
+        let from = alert_smtp.map_or(&self.config.gmail_from, |smtp| &smtp.from);
+        let body = system_body(event, details);
+
+        let mut email_builder = Message::builder()
+            .from(from.parse()?)
+            .subject(with_test_prefix(format!("weather-checker: {}", event)));
+        if self.config.alert_to.is_empty() {
+            email_builder = email_builder.to(self.config.email_to.parse()?);
+        }
+        for to in &self.config.alert_to {
+            email_builder = email_builder.to(to.parse()?);
+        }
+
+        let email = email_builder
+            .header(ContentType::TEXT_PLAIN)
+            .body(body.clone())?;
+
+        self.send_through(alert_smtp, &email, &body)?;
+
+        tracing::info!("Sent alert: {}", event);
+
+        Ok(())
+    }
 }
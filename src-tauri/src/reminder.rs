@@ -0,0 +1,364 @@
+use crate::clock;
+use crate::config::{Config, MonitorConfig};
+use crate::database::{CityReport, Database, NewOutboxMessage};
+use crate::dispatcher::Dispatcher;
+use crate::error::{Result, WeatherCheckerError};
+use crate::notification::{EmailNotifier, ReminderNotification, REMINDER_MESSAGE_TYPE};
+use crate::severity::{self, Severity};
+use chrono::{DateTime, Utc};
+
+/// Queue "still in effect" reminders for long-running warnings and dispatch them
+/// Each active kind gets one reminder per elapsed interval of its severity; the dedup key
+/// includes the interval number, so a scan never repeats a reminder and a cancelled
+/// kind (no longer active) gets no further ones. A 特別警報 is repeated at the shorter
+/// emergency interval until a notification about it is acknowledged.
+pub async fn run_reminders() -> Result<()> {
+    let config = Config::from_env()?;
+    let monitor_config = MonitorConfig::load(MonitorConfig::default_path())?;
+    let db = Database::new().await?;
+    scan_reminders(&db, &config, &monitor_config).await
+}
+
+/// Queue the reminders due now and dispatch them
+pub async fn scan_reminders(
+    db: &Database,
+    config: &Config,
+    monitor_config: &MonitorConfig,
+) -> Result<()> {
+    if queue_reminders(db, config, monitor_config).await? > 0 {
+        let groups = monitor_config.recipient_groups(config);
+        let notifier = EmailNotifier::new(config.clone());
+        Dispatcher::new(db, &notifier, &groups)
+            .with_ack(config.ack.as_ref())
+            .dispatch_pending()
+            .await?;
+    }
+    Ok(())
+}
+
+/// Queue a reminder for every active kind with an elapsed interval; returns how many
+async fn queue_reminders(
+    db: &Database,
+    config: &Config,
+    monitor_config: &MonitorConfig,
+) -> Result<usize> {
+    let now = clock::now();
+    let mut queued = 0;
+
+    for region in &monitor_config.monitored_regions {
+        for city in region.city_names() {
+            let rules = region.notification_rules(city);
+
+            for record in db.get_active_city_reports(&region.lmo, city).await? {
+                let Some((_, severity)) = severity::classify(&record.warning_kind) else {
+                    continue;
+                };
+                if !rules.allows_kind(&record.warning_kind) {
+                    continue;
+                }
+                let Some(active_since) = db.get_active_since(&record).await? else {
+                    continue;
+                };
+
+                let key_prefix = reminder_key_prefix(&record, active_since);
+                let acknowledged = severity >= Severity::Warning
+                    && db.is_period_acknowledged(&record, &key_prefix).await?;
+                let urgent = match config.emergency_reminder_interval() {
+                    Some(interval) if severity == Severity::Emergency && !acknowledged => {
+                        Some(interval)
+                    }
+                    _ => None,
+                };
+                // Urgent repeats are numbered separately so they never use up regular keys
+                let (interval, number_prefix) = match urgent {
+                    Some(interval) => (interval, "u"),
+                    None => match config.reminder_interval(severity) {
+                        Some(interval) => (interval, ""),
+                        None => continue,
+                    },
+                };
+
+                let elapsed_intervals = (now - active_since).num_seconds() / interval.num_seconds();
+                if elapsed_intervals < 1 {
+                    continue;
+                }
+
+                let message = reminder_message(
+                    &record,
+                    region.get_city_url(city),
+                    active_since,
+                    format!("{}{}{}", key_prefix, number_prefix, elapsed_intervals),
+                    now,
+                    acknowledged,
+                )?;
+                if db.enqueue_outbox_message(&message).await? {
+                    tracing::info!(
+                        "Queued reminder for {} - {} (in effect since {})",
+                        city,
+                        record.warning_kind,
+                        active_since
+                    );
+                    queued += 1;
+                }
+            }
+        }
+    }
+
+    Ok(queued)
+}
+
+/// Dedup key shared by every reminder of one active period, up to the reminder number
+fn reminder_key_prefix(record: &CityReport, active_since: DateTime<Utc>) -> String {
+    format!(
+        "{}|{}|{}|{}|{}|",
+        REMINDER_MESSAGE_TYPE,
+        record.lmo,
+        record.city,
+        record.warning_kind,
+        active_since.timestamp()
+    )
+}
+
+fn reminder_message(
+    record: &CityReport,
+    jma_url: Option<&str>,
+    active_since: DateTime<Utc>,
+    dedup_key: String,
+    now: DateTime<Utc>,
+    acknowledged: bool,
+) -> Result<NewOutboxMessage> {
+    let payload = ReminderNotification {
+        lmo: record.lmo.clone(),
+        city: record.city.clone(),
+        warning_kind: record.warning_kind.clone(),
+        status: record.status.clone(),
+        jma_url: jma_url.map(String::from),
+        active_since,
+        checked_at: now,
+        acknowledged,
+    };
+
+    Ok(NewOutboxMessage {
+        dedup_key,
+        message_type: REMINDER_MESSAGE_TYPE.to_string(),
+        payload: serde_json::to_string(&payload)
+            .map_err(|e| WeatherCheckerError::Other(format!("Failed to encode payload: {}", e)))?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{ManualClock, TEST_CLOCK_LOCK};
+    use crate::database::CityReportChange;
+    use chrono::Duration;
+    use std::sync::Arc;
+
+    const LMO: &str = "静岡地方気象台";
+    const CITY: &str = "裾野市";
+
+    struct Fixture {
+        db: Database,
+        db_path: String,
+        config: Config,
+        monitor_config: MonitorConfig,
+        clock: Arc<ManualClock>,
+        start: DateTime<Utc>,
+        last_id: i64,
+    }
+
+    impl Fixture {
+        async fn new() -> Self {
+            let mut config = crate::config::test_config();
+            config.reminder_advisory_hours = 1;
+            config.reminder_warning_hours = 6;
+            config.reminder_emergency_hours = 3;
+            config.emergency_reminder_minutes = 30;
+
+            let monitor_config = serde_yaml::from_str(&format!(
+                "monitored_regions:\n  - lmo: {}\n    cities:\n      - name: {}\n",
+                LMO, CITY
+            ))
+            .unwrap();
+
+            let db_path = std::env::temp_dir()
+                .join(format!("reminder-{:016x}.sqlite3", rand::random::<u64>()))
+                .to_string_lossy()
+                .to_string();
+            let db = Database::connect(&db_path).await.unwrap();
+            db.init_schema().await.unwrap();
+
+            let start = "2025-07-01T00:00:00Z".parse().unwrap();
+            let clock = Arc::new(ManualClock::new(start));
+            clock::set_clock(clock.clone());
+
+            Self {
+                db,
+                db_path,
+                config,
+                monitor_config,
+                clock,
+                start,
+                last_id: 0,
+            }
+        }
+
+        /// Record a status change of `kind` at `at`, queuing its city notification
+        async fn report(&self, kind: &str, status: &str, at: DateTime<Utc>) {
+            self.clock.set(at);
+            let previous = self.db.get_city_report(LMO, CITY, kind).await.unwrap();
+            let report = CityReport {
+                id: None,
+                xml_file: format!("{}_0_VPWW54_220000.xml", at.format("%Y%m%d%H%M%S")),
+                lmo: LMO.to_string(),
+                city: CITY.to_string(),
+                warning_kind: kind.to_string(),
+                status: status.to_string(),
+                created_at: None,
+                is_delete: false,
+            };
+            let message = NewOutboxMessage {
+                dedup_key: format!("city|{}|{}", kind, report.xml_file),
+                message_type: "city".to_string(),
+                payload: "{}".to_string(),
+            };
+            let change = CityReportChange {
+                previous: previous.as_ref(),
+                report: &report,
+                notified: true,
+            };
+            self.db
+                .commit_city_report_changes(&[change], &at, Some(&message))
+                .await
+                .unwrap();
+        }
+
+        /// Run a reminder scan at `start + offset`; returns the dedup keys it queued
+        async fn scan(&mut self, offset: Duration) -> Vec<String> {
+            self.clock.set(self.start + offset);
+            queue_reminders(&self.db, &self.config, &self.monitor_config)
+                .await
+                .unwrap();
+
+            let messages = self
+                .db
+                .get_outbox_messages_after(self.last_id)
+                .await
+                .unwrap();
+            if let Some(last) = messages.last() {
+                self.last_id = last.id;
+            }
+            messages
+                .into_iter()
+                .filter(|m| m.message_type == REMINDER_MESSAGE_TYPE)
+                .map(|m| m.dedup_key)
+                .collect()
+        }
+
+        fn key(&self, kind: &str, since: DateTime<Utc>, number: &str) -> String {
+            format!(
+                "{}|{}|{}|{}|{}|{}",
+                REMINDER_MESSAGE_TYPE,
+                LMO,
+                CITY,
+                kind,
+                since.timestamp(),
+                number
+            )
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = Database::remove_files(&self.db_path);
+        }
+    }
+
+    #[tokio::test]
+    async fn reminds_once_per_elapsed_interval_of_each_severity() {
+        let _lock = TEST_CLOCK_LOCK.lock().await;
+        let mut fx = Fixture::new().await;
+        let start = fx.start;
+        fx.report("大雨注意報", "発表", start).await;
+        fx.report("洪水警報", "発表", start).await;
+
+        assert!(fx.scan(Duration::minutes(59)).await.is_empty());
+        assert_eq!(
+            fx.scan(Duration::hours(1)).await,
+            vec![fx.key("大雨注意報", start, "1")]
+        );
+        // The same interval is never reminded twice
+        assert!(fx.scan(Duration::minutes(90)).await.is_empty());
+        assert_eq!(
+            fx.scan(Duration::hours(6)).await,
+            vec![
+                fx.key("大雨注意報", start, "6"),
+                fx.key("洪水警報", start, "1")
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn repeats_unacknowledged_emergency_at_the_urgent_interval() {
+        let _lock = TEST_CLOCK_LOCK.lock().await;
+        let mut fx = Fixture::new().await;
+        let start = fx.start;
+        fx.report("大雨特別警報", "発表", start).await;
+
+        assert!(fx.scan(Duration::minutes(29)).await.is_empty());
+        assert_eq!(
+            fx.scan(Duration::minutes(30)).await,
+            vec![fx.key("大雨特別警報", start, "u1")]
+        );
+        assert_eq!(
+            fx.scan(Duration::minutes(60)).await,
+            vec![fx.key("大雨特別警報", start, "u2")]
+        );
+
+        // Once the warning notification is acknowledged, the regular interval applies
+        let issued = fx.db.get_outbox_messages_after(0).await.unwrap();
+        let token = fx.db.ensure_ack_token(issued[0].id, "test").await.unwrap();
+        fx.db.acknowledge(&token, "tester", "test").await.unwrap();
+
+        assert!(fx.scan(Duration::minutes(150)).await.is_empty());
+        let keys = fx.scan(Duration::hours(3)).await;
+        assert_eq!(keys, vec![fx.key("大雨特別警報", start, "1")]);
+        let reminder = fx.db.get_outbox_messages_after(0).await.unwrap();
+        let payload: ReminderNotification =
+            serde_json::from_str(&reminder.last().unwrap().payload).unwrap();
+        assert!(payload.acknowledged);
+    }
+
+    #[tokio::test]
+    async fn keys_each_active_period_by_its_start() {
+        let _lock = TEST_CLOCK_LOCK.lock().await;
+        let mut fx = Fixture::new().await;
+        let start = fx.start;
+        fx.report("洪水警報", "発表", start).await;
+        assert_eq!(
+            fx.scan(Duration::hours(6)).await,
+            vec![fx.key("洪水警報", start, "1")]
+        );
+
+        // Continuing does not start a new period
+        fx.report("洪水警報", "継続", start + Duration::hours(7))
+            .await;
+        assert_eq!(
+            fx.scan(Duration::hours(12)).await,
+            vec![fx.key("洪水警報", start, "2")]
+        );
+
+        // A cancelled kind gets no further reminders
+        fx.report("洪水警報", "解除", start + Duration::hours(13))
+            .await;
+        assert!(fx.scan(Duration::hours(18)).await.is_empty());
+
+        // Re-issued, it is a new period numbered from its own start
+        let reissued = start + Duration::hours(20);
+        fx.report("洪水警報", "発表", reissued).await;
+        assert_eq!(
+            fx.scan(Duration::hours(26)).await,
+            vec![fx.key("洪水警報", reissued, "1")]
+        );
+    }
+}
@@ -0,0 +1,330 @@
+use crate::clock::{self, ManualClock};
+use crate::config::{Config, MonitorConfig};
+use crate::database::Database;
+use crate::error::{Result, WeatherCheckerError};
+use crate::jma_feed::{self, JMAFeed, WarningData};
+use crate::notification::Notification;
+use crate::reminder;
+use crate::weather_checker::WeatherChecker;
+use chrono::{DateTime, Duration, DurationRound, FixedOffset, Utc};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Interval of the reminder scan, as scheduled by the live service (`0 5/10 * * * *`)
+const REMINDER_SCAN_MINUTES: i64 = 10;
+const REMINDER_SCAN_OFFSET_MINUTES: i64 = 5;
+
+/// A recorded VPWW54 report found in the replay directories
+struct RecordedReport {
+    lmo: String,
+    filename: String,
+    report_datetime: DateTime<Utc>,
+    warnings: Vec<WarningData>,
+    control_datetime: DateTime<Utc>,
+}
+
+/// Replay recorded extra.xml and VPWW54 files and print the notifications they produce
+/// Reports are applied in report-time order against an empty scratch database, with the
+/// clock set to each report's time; reminder scans run every 10 minutes in between, as
+/// they would live. Ends with the warning periods of every monitored city.
+/// Runs in dry-run mode, so nothing is emailed. extra.xml files only
+/// attribute reports to LMOs (falling back to `<PublishingOffice>`); the live cleanup
+/// for LMOs absent from extra.xml is not replayed.
+pub async fn run(dirs: &[String]) -> Result<()> {
+    if dirs.is_empty() {
+        return Err(WeatherCheckerError::Config(
+            "Usage: tauri-weather-checker replay <dir>...".into(),
+        ));
+    }
+
+    let config = Config::from_env()?;
+    let monitor_config = MonitorConfig::load(MonitorConfig::default_path())?;
+    let notifications = replay(&config, &monitor_config, dirs).await?;
+    println!("Replay finished: {} notifications", notifications);
+    Ok(())
+}
+
+/// Replay the directories into a fresh database at `config.db_path`
+/// Returns the number of notifications printed.
+async fn replay(config: &Config, monitor_config: &MonitorConfig, dirs: &[String]) -> Result<usize> {
+    Database::remove_files(&config.db_path)?;
+    let db = Database::connect(&config.db_path).await?;
+    db.init_schema().await?;
+
+    let feed = JMAFeed::new(config.clone())?;
+    let reports = load_reports(&feed, dirs).await?;
+    let Some(first) = reports.first() else {
+        println!("No VPWW54 reports found in {}", dirs.join(", "));
+        return Ok(0);
+    };
+    tracing::info!(
+        "Replaying {} reports against {}",
+        reports.len(),
+        config.db_path
+    );
+
+    let replay_clock = Arc::new(ManualClock::new(first.report_datetime));
+    clock::set_clock(replay_clock.clone());
+
+    let checker = WeatherChecker::with_config(config.clone(), monitor_config.clone()).await?;
+    let mut next_scan = first_reminder_scan(first.report_datetime);
+    let mut last_id = 0;
+    let mut notifications = 0;
+
+    for report in reports {
+        while next_scan <= report.report_datetime {
+            replay_clock.set(next_scan);
+            reminder::scan_reminders(&db, config, monitor_config).await?;
+            let printed = print_new_notifications(&db, &mut last_id, || {
+                format!("{} reminder scan", jst(next_scan))
+            })
+            .await?;
+            notifications += printed;
+            next_scan += Duration::minutes(REMINDER_SCAN_MINUTES);
+        }
+
+        replay_clock.set(report.report_datetime);
+        let latest = (
+            report.warnings,
+            report.filename.clone(),
+            report.control_datetime,
+        );
+        if !checker.apply_recorded_report(&report.lmo, latest).await? {
+            continue;
+        }
+        let printed = print_new_notifications(&db, &mut last_id, || {
+            format!(
+                "{} {} ({})",
+                jst(report.report_datetime),
+                report.filename,
+                report.lmo
+            )
+        })
+        .await?;
+        notifications += printed;
+    }
+
+    print_warning_periods(&db, monitor_config).await?;
+    Ok(notifications)
+}
+
+/// Collect VPWW54 reports from the directories, ordered by report time
+/// A file present in several directories (e.g. data/xml and data/deleted) is read once.
+async fn load_reports(feed: &JMAFeed, dirs: &[String]) -> Result<Vec<RecordedReport>> {
+    let mut files = Vec::new();
+    for dir in dirs {
+        collect_xml_files(Path::new(dir), &mut files)?;
+    }
+    files.sort();
+
+    let mut lmo_by_file: HashMap<String, String> = HashMap::new();
+    let mut seen_files: HashSet<String> = HashSet::new();
+    let mut reports: Vec<RecordedReport> = Vec::new();
+
+    for path in files {
+        let content = std::fs::read(&path)?;
+
+        // Atom feeds (extra.xml) map report files to the LMO that published them
+        if jma_feed::feed_updated(&content).is_some() {
+            for entry in feed.parse_extra_xml(&content).await? {
+                lmo_by_file.insert(entry.filename, entry.lmo);
+            }
+            continue;
+        }
+
+        let filename = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        if !seen_files.insert(filename.clone()) {
+            continue;
+        }
+
+        let data = match JMAFeed::parse_vpww54_data(&String::from_utf8_lossy(&content)) {
+            Ok(data) => data,
+            Err(e) => {
+                tracing::debug!("Skipping {}: {}", path.display(), e);
+                continue;
+            }
+        };
+        let lmo = data.control.publishing_office.clone();
+        let report_datetime = data.head.report_datetime;
+        let (warnings, control_datetime) = JMAFeed::warning_data(data);
+
+        reports.push(RecordedReport {
+            lmo,
+            filename,
+            report_datetime,
+            warnings,
+            control_datetime,
+        });
+    }
+
+    for report in &mut reports {
+        if let Some(lmo) = lmo_by_file.get(&report.filename) {
+            report.lmo = lmo.clone();
+        }
+    }
+    reports.sort_by(|a, b| {
+        a.report_datetime
+            .cmp(&b.report_datetime)
+            .then_with(|| a.filename.cmp(&b.filename))
+    });
+    Ok(reports)
+}
+
+fn collect_xml_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_xml_files(&path, files)?;
+        } else if path.extension().is_some_and(|e| e == "xml") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Print the notifications queued since `last_id` under a heading; returns how many
+async fn print_new_notifications(
+    db: &Database,
+    last_id: &mut i64,
+    heading: impl FnOnce() -> String,
+) -> Result<usize> {
+    let messages = db.get_outbox_messages_after(*last_id).await?;
+    let Some(last) = messages.last() else {
+        return Ok(0);
+    };
+    *last_id = last.id;
+
+    println!("=== {}", heading());
+    let mut printed = 0;
+    for message in &messages {
+        match Notification::decode(&message.message_type, &message.payload) {
+            Some(Ok(notification)) => {
+                println!(
+                    "Subject: {}\n{}\n",
+                    notification.subject(),
+                    notification.body()
+                );
+                printed += 1;
+            }
+            Some(Err(e)) => tracing::warn!("Cannot decode {}: {}", message.dedup_key, e),
+            None => {}
+        }
+    }
+    Ok(printed)
+}
+
+/// Print the periods each warning kind was in effect for every monitored city
+/// Periods still open at the end of the replay are measured up to the last report.
+async fn print_warning_periods(db: &Database, monitor_config: &MonitorConfig) -> Result<()> {
+    let now = clock::now();
+
+    println!("=== Warning periods");
+    for region in &monitor_config.monitored_regions {
+        for city in region.city_names() {
+            for period in db
+                .get_warning_periods(&region.lmo, city, None, None, None)
+                .await?
+            {
+                let end = match period.ended_at {
+                    Some(ended_at) => jst(ended_at),
+                    None => "in effect".to_string(),
+                };
+                println!(
+                    "{} {} {}: {} - {} ({}m)",
+                    period.lmo,
+                    period.city,
+                    period.warning_kind,
+                    jst(period.started_at),
+                    end,
+                    period.duration(now).num_minutes()
+                );
+            }
+        }
+    }
+    println!();
+    Ok(())
+}
+
+/// First reminder scan at or after `start` on the live schedule (minute 5, 15, 25, ...)
+fn first_reminder_scan(start: DateTime<Utc>) -> DateTime<Utc> {
+    let offset = Duration::minutes(REMINDER_SCAN_OFFSET_MINUTES);
+    let slot = (start - offset)
+        .duration_trunc(Duration::minutes(REMINDER_SCAN_MINUTES))
+        .unwrap_or(start)
+        + offset;
+    if slot < start {
+        slot + Duration::minutes(REMINDER_SCAN_MINUTES)
+    } else {
+        slot
+    }
+}
+
+fn jst(datetime: DateTime<Utc>) -> String {
+    datetime
+        .with_timezone(&FixedOffset::east_opt(9 * 3600).unwrap())
+        .format("%Y/%m/%d %H:%M:%S JST")
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::TEST_CLOCK_LOCK;
+
+    #[tokio::test]
+    async fn replays_fixture_reports_with_reminders() {
+        let _lock = TEST_CLOCK_LOCK.lock().await;
+        let dir = std::env::temp_dir().join(format!("replay-{:016x}", rand::random::<u64>()));
+        let mut config = crate::config::test_config();
+        config.dry_run = true;
+        config.data_dir = dir.join("xml").to_string_lossy().to_string();
+        config.deleted_dir = dir.join("deleted").to_string_lossy().to_string();
+        config.db_path = dir.join("weather.sqlite3").to_string_lossy().to_string();
+        config.reminder_warning_hours = 6;
+        let monitor_config: MonitorConfig = serde_yaml::from_str(
+            "monitored_regions:\n  - lmo: 静岡地方気象台\n    cities:\n      - name: 裾野市\n      - name: 御殿場市\n",
+        )
+        .unwrap();
+        let fixtures = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/vpww54");
+
+        // Per city: 注意報 issued, upgraded to 警報, one 6-hour reminder, then cancelled
+        let notifications = replay(&config, &monitor_config, &[fixtures.to_string()])
+            .await
+            .unwrap();
+        assert_eq!(notifications, 8);
+
+        let db = Database::connect(&config.db_path).await.unwrap();
+        for city in ["裾野市", "御殿場市"] {
+            let periods: Vec<_> = db
+                .get_warning_periods("静岡地方気象台", city, None, None, None)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|p| (p.warning_kind, jst(p.started_at), p.ended_at.map(jst)))
+                .collect();
+            assert_eq!(
+                periods,
+                vec![
+                    (
+                        "大雨注意報".to_string(),
+                        "2025/06/01 10:00:00 JST".to_string(),
+                        Some("2025/06/01 12:00:00 JST".to_string())
+                    ),
+                    (
+                        "大雨警報".to_string(),
+                        "2025/06/01 12:00:00 JST".to_string(),
+                        Some("2025/06/01 23:00:00 JST".to_string())
+                    ),
+                ],
+                "{}",
+                city
+            );
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
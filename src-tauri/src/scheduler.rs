@@ -1,26 +1,94 @@
+use crate::ack;
 use crate::cleanup::Cleanup;
+use crate::clock;
 use crate::config::Config;
-use crate::error::Result;
+use crate::dispatcher;
+use crate::error::{Result, WeatherCheckerError};
+use crate::freshness;
+use crate::lifecycle;
 use crate::notification::EmailNotifier;
-use crate::weather_checker::WeatherChecker;
-use std::sync::atomic::{AtomicU32, Ordering};
+use crate::reminder;
+use crate::snapshot;
+use crate::weather_checker::{CycleResult, WeatherChecker};
+use crate::websub;
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU32, Ordering};
+use std::sync::Mutex;
 use tokio_cron_scheduler::{Job, JobScheduler};
 use tokio_util::sync::CancellationToken;
 
 static CONSECUTIVE_FAILURES: AtomicU32 = AtomicU32::new(0);
-const FAILURE_WARNING_THRESHOLD: u32 = 3;
+/// Unix time of the first failure in the current run of failed checks
+static FIRST_FAILURE_AT: AtomicI64 = AtomicI64::new(0);
+/// Used when the configuration cannot be loaded at startup
+const DEFAULT_FAILURE_ALERT_THRESHOLD: u32 = 3;
+static SNAPSHOT_PENDING: AtomicBool = AtomicBool::new(true);
+/// Failure streak per LMO; an LMO is dropped once it succeeds or has no report
+static REGION_FAILURES: Mutex<BTreeMap<String, RegionFailure>> = Mutex::new(BTreeMap::new());
+/// Summary of the last polled cycle, repeated in heartbeats of cycles skipped for WebSub
+static LAST_SUMMARY: Mutex<String> = Mutex::new(String::new());
+
+/// Consecutive failed cycles of one LMO while other regions keep succeeding
+struct RegionFailure {
+    cycles: u32,
+    /// Unix time of the first failed cycle
+    since: i64,
+    last_error: String,
+    /// Whether a `region failing` alert went out for this streak
+    alerted: bool,
+}
 
 pub async fn start_scheduler(cancel_token: CancellationToken) -> Result<()> {
     tracing::info!("Starting scheduler...");
 
+    // How the previous run ended, reported in the startup notification
+    let previous_run = lifecycle::record_start();
+
+    // Polling is skipped while WebSub pushes keep arriving within this window
+    let mut push_fallback_secs: Option<u64> = None;
+    let mut failure_threshold = DEFAULT_FAILURE_ALERT_THRESHOLD;
+
     // Send startup notification (non-fatal)
     match Config::from_env() {
         Ok(config) => {
+            if config.dry_run {
+                tracing::warn!(
+                    "Dry run: emails are logged, not sent; state is kept in {}",
+                    config.db_path
+                );
+            }
+
+            failure_threshold = config.failure_alert_threshold;
+
+            // Start the WebSub subscriber if push delivery is enabled
+            if let Some(websub_config) = &config.websub {
+                push_fallback_secs = Some(websub_config.fallback_secs);
+                let websub_token = cancel_token.clone();
+                let websub_app_config = config.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = websub::run(websub_app_config, websub_token).await {
+                        tracing::error!("WebSub subscriber failed, relying on polling: {}", e);
+                    }
+                });
+            }
+
+            // Serve the acknowledgement endpoint if the ack workflow is enabled
+            if let Some(ack_config) = config.ack.clone() {
+                let ack_token = cancel_token.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = ack::serve(&ack_config, ack_token).await {
+                        tracing::error!("Acknowledgement endpoint failed: {}", e);
+                    }
+                });
+            }
+
+            let mut details = "Service started successfully".to_string();
+            if let Some(previous_run) = &previous_run {
+                details.push_str(&format!(". {}", previous_run.describe()));
+            }
             let notifier = EmailNotifier::new(config);
-            if let Err(e) = notifier
-                .send_system_notification("started", "Service started successfully")
-                .await
-            {
+            if let Err(e) = notifier.send_system_notification("started", &details).await {
                 tracing::warn!("Failed to send startup notification: {}", e);
             }
         }
@@ -31,56 +99,66 @@ pub async fn start_scheduler(cancel_token: CancellationToken) -> Result<()> {
 
     let mut scheduler = JobScheduler::new().await?;
 
+    // The previous run's last heartbeat marks which warnings are new in the startup snapshot
+    let last_seen = read_heartbeat();
+
     // Run weather check immediately on startup
     tracing::info!("Running initial weather check...");
-    match run_weather_check().await {
-        Ok(()) => {
-            CONSECUTIVE_FAILURES.store(0, Ordering::Relaxed);
-            write_heartbeat();
-        }
-        Err(e) => {
-            let count = CONSECUTIVE_FAILURES.fetch_add(1, Ordering::Relaxed) + 1;
-            tracing::error!("Initial weather check failed: {}", e);
-            if count >= FAILURE_WARNING_THRESHOLD {
-                tracing::warn!(
-                    "Weather check has failed {} consecutive times",
-                    count
-                );
-            }
-        }
-    }
+    finish_cycle(run_weather_check().await, failure_threshold, last_seen).await;
 
     // Schedule weather check every 10 minutes
-    let weather_job = Job::new_async("0 */10 * * * *", |_uuid, _lock| {
-        Box::pin(async {
-            match run_weather_check().await {
-                Ok(()) => {
-                    let prev = CONSECUTIVE_FAILURES.swap(0, Ordering::Relaxed);
-                    if prev >= FAILURE_WARNING_THRESHOLD {
-                        tracing::info!(
-                            "Weather check recovered after {} consecutive failures",
-                            prev
-                        );
-                    }
-                    write_heartbeat();
-                }
-                Err(e) => {
-                    let count = CONSECUTIVE_FAILURES.fetch_add(1, Ordering::Relaxed) + 1;
-                    tracing::error!("Weather check failed: {}", e);
-                    if count >= FAILURE_WARNING_THRESHOLD {
-                        tracing::warn!(
-                            "Weather check has failed {} consecutive times",
-                            count
-                        );
-                    }
-                }
+    let weather_job = Job::new_async("0 */10 * * * *", move |_uuid, _lock| {
+        Box::pin(async move {
+            if push_fallback_secs.is_some_and(websub::push_is_fresh) {
+                tracing::debug!("Recent WebSub push received, skipping polling cycle");
+                let summary = LAST_SUMMARY.lock().unwrap().clone();
+                write_heartbeat(&summary, check_freshness().await.as_deref());
+                return;
             }
+
+            finish_cycle(run_weather_check().await, failure_threshold, last_seen).await;
         })
     })?;
 
     scheduler.add(weather_job).await?;
     tracing::info!("Scheduled weather check every 10 minutes");
 
+    // Retry queued notifications every minute
+    let dispatch_job = Job::new_async("0 * * * * *", |_uuid, _lock| {
+        Box::pin(async {
+            if let Err(e) = dispatcher::run_dispatch().await {
+                tracing::error!("Notification dispatch failed: {}", e);
+            }
+        })
+    })?;
+
+    scheduler.add(dispatch_job).await?;
+    tracing::info!("Scheduled notification dispatch every minute");
+
+    // Remind about long-running warnings, between weather checks
+    let reminder_job = Job::new_async("0 5/10 * * * *", |_uuid, _lock| {
+        Box::pin(async {
+            if let Err(e) = reminder::run_reminders().await {
+                tracing::error!("Reminder scan failed: {}", e);
+            }
+        })
+    })?;
+
+    scheduler.add(reminder_job).await?;
+    tracing::info!("Scheduled reminder scan every 10 minutes");
+
+    // Escalate unacknowledged 警報-level notifications (no-op unless ACK_ENABLED)
+    let escalation_job = Job::new_async("30 * * * * *", |_uuid, _lock| {
+        Box::pin(async {
+            if let Err(e) = ack::run_escalations().await {
+                tracing::error!("Escalation check failed: {}", e);
+            }
+        })
+    })?;
+
+    scheduler.add(escalation_job).await?;
+    tracing::info!("Scheduled acknowledgement escalation check every minute");
+
     // Schedule cleanup daily at 01:00
     let cleanup_job = Job::new_async("0 0 1 * * *", |_uuid, _lock| {
         Box::pin(async {
@@ -104,13 +182,186 @@ pub async fn start_scheduler(cancel_token: CancellationToken) -> Result<()> {
     Ok(())
 }
 
-async fn run_weather_check() -> Result<()> {
+/// Run one check cycle
+async fn run_weather_check() -> Result<CycleResult> {
     let start = std::time::Instant::now();
     let checker = WeatherChecker::new().await?;
-    checker.run_check().await?;
+    let cycle = checker.run_check().await?;
     let elapsed = start.elapsed();
     tracing::info!("Weather check completed in {}ms", elapsed.as_millis());
-    Ok(())
+    Ok(cycle)
+}
+
+/// Record the outcome of a check cycle: failure counters, heartbeat and startup snapshot
+/// The cycle counts as failed only when every attempted region failed; partial
+/// failures are logged and kept per LMO in the heartbeat, which they still refresh.
+async fn finish_cycle(
+    result: Result<CycleResult>,
+    threshold: u32,
+    last_seen: Option<DateTime<Utc>>,
+) {
+    let cycle = match result {
+        Ok(cycle) => cycle,
+        Err(e) => {
+            tracing::error!("Weather check failed: {}", e);
+            record_failure(&e, threshold).await;
+            return;
+        }
+    };
+
+    let (summary, alerts) = track_region_failures(&cycle, threshold);
+    for (event, details) in alerts {
+        send_alert(event, &details).await;
+    }
+    if cycle.is_total_failure() {
+        let e = WeatherCheckerError::Other(format!("All regions failed: {}", cycle.summary()));
+        tracing::error!("Weather check failed: {}", e);
+        record_failure(&e, threshold).await;
+        return;
+    }
+    if !cycle.failed.is_empty() {
+        tracing::warn!("Weather check partially failed: {}", summary);
+    }
+
+    record_success(threshold).await;
+    write_heartbeat(&summary, check_freshness().await.as_deref());
+    *LAST_SUMMARY.lock().unwrap() = summary;
+    send_startup_snapshot(last_seen).await;
+}
+
+/// Update the per-LMO failure streaks and return the cycle summary including them
+/// e.g. `succeeded=2 failed=1 skipped=0; 金沢地方気象台: <error>; 金沢地方気象台 failing for 4 cycles`,
+/// together with the `region failing`/`region recovered` alerts to send. A region is
+/// alerted once its streak reaches `threshold`, unless every region failed, which is
+/// reported by `record_failure` instead.
+fn track_region_failures(
+    cycle: &CycleResult,
+    threshold: u32,
+) -> (String, Vec<(&'static str, String)>) {
+    let now = clock::now().timestamp();
+    let mut alerts = Vec::new();
+    let mut failures = REGION_FAILURES.lock().unwrap();
+
+    for lmo in cycle.succeeded.iter().chain(&cycle.skipped) {
+        let Some(failure) = failures.remove(lmo) else {
+            continue;
+        };
+        if failure.alerted {
+            tracing::info!(
+                "{} recovered after {} consecutive failed cycles",
+                lmo,
+                failure.cycles
+            );
+            alerts.push((
+                "region recovered",
+                format!(
+                    "{} checks succeeded again after {} consecutive failed cycles over {} minutes",
+                    lmo,
+                    failure.cycles,
+                    (now - failure.since) / 60
+                ),
+            ));
+        }
+    }
+
+    for (lmo, error) in &cycle.failed {
+        let failure = failures.entry(lmo.clone()).or_insert(RegionFailure {
+            cycles: 0,
+            since: now,
+            last_error: String::new(),
+            alerted: false,
+        });
+        failure.cycles += 1;
+        failure.last_error = error.clone();
+        if failure.cycles >= threshold && !failure.alerted && !cycle.is_total_failure() {
+            failure.alerted = true;
+            tracing::warn!("{} has failed {} consecutive cycles", lmo, failure.cycles);
+            alerts.push((
+                "region failing",
+                format!(
+                    "{} checks failing for {} minutes ({} consecutive failed cycles). Last error: {}",
+                    lmo,
+                    (now - failure.since) / 60,
+                    failure.cycles,
+                    failure.last_error
+                ),
+            ));
+        }
+    }
+
+    let mut summary = cycle.summary();
+    for (lmo, failure) in failures.iter() {
+        summary.push_str(&format!("; {} failing for {} cycles", lmo, failure.cycles));
+    }
+    (summary, alerts)
+}
+
+/// Count a failed check and alert once `threshold` consecutive checks have failed
+async fn record_failure(error: &WeatherCheckerError, threshold: u32) {
+    let count = CONSECUTIVE_FAILURES.fetch_add(1, Ordering::Relaxed) + 1;
+    if count == 1 {
+        FIRST_FAILURE_AT.store(clock::now().timestamp(), Ordering::Relaxed);
+    }
+    if count < threshold {
+        return;
+    }
+
+    tracing::warn!("Weather check has failed {} consecutive times", count);
+    if count == threshold {
+        let details = format!(
+            "Weather checks failing for {} minutes ({} consecutive failures). Last error: {}",
+            failing_minutes(),
+            count,
+            error
+        );
+        send_alert("checks failing", &details).await;
+    }
+}
+
+/// Reset the failure count, announcing the recovery if a failure alert went out
+async fn record_success(threshold: u32) {
+    let prev = CONSECUTIVE_FAILURES.swap(0, Ordering::Relaxed);
+    if prev < threshold {
+        return;
+    }
+
+    tracing::info!(
+        "Weather check recovered after {} consecutive failures",
+        prev
+    );
+    let details = format!(
+        "Weather checks succeeded again after {} consecutive failures over {} minutes",
+        prev,
+        failing_minutes()
+    );
+    send_alert("checks recovered", &details).await;
+}
+
+/// Minutes since the first failure of the current run of failed checks
+fn failing_minutes() -> i64 {
+    let first = FIRST_FAILURE_AT.load(Ordering::Relaxed);
+    (clock::now().timestamp() - first) / 60
+}
+
+/// Send an alert through the alert channel; failures are only logged
+async fn send_alert(event: &str, details: &str) {
+    let result = match Config::from_env() {
+        Ok(config) => EmailNotifier::new(config).send_alert(event, details).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        tracing::error!("Failed to send {} alert: {}", event, e);
+    }
+}
+
+/// Send the startup snapshot after the first successful cycle only
+async fn send_startup_snapshot(last_seen: Option<DateTime<Utc>>) {
+    if !SNAPSHOT_PENDING.swap(false, Ordering::Relaxed) {
+        return;
+    }
+    if let Err(e) = snapshot::send_startup_snapshot(last_seen).await {
+        tracing::warn!("Failed to send startup snapshot: {}", e);
+    }
 }
 
 async fn run_cleanup() -> Result<()> {
@@ -118,8 +369,21 @@ async fn run_cleanup() -> Result<()> {
     cleanup.run_cleanup().await
 }
 
-fn write_heartbeat() {
-    let heartbeat_path = std::path::Path::new("data/heartbeat");
+/// Check data freshness for the heartbeat; a failed check is logged and leaves the line out
+async fn check_freshness() -> Option<String> {
+    match freshness::run_freshness_check().await {
+        Ok(summary) => Some(summary),
+        Err(e) => {
+            tracing::warn!("Freshness check failed: {}", e);
+            None
+        }
+    }
+}
+
+/// Write the heartbeat file: UTC timestamp on the first line, last cycle summary on the
+/// second and, when available, the data freshness summary on the third
+fn write_heartbeat(summary: &str, freshness: Option<&str>) {
+    let heartbeat_path = heartbeat_path();
     if let Some(parent) = heartbeat_path.parent() {
         if !parent.exists() {
             if let Err(e) = std::fs::create_dir_all(parent) {
@@ -128,8 +392,31 @@ fn write_heartbeat() {
             }
         }
     }
-    let timestamp = chrono::Utc::now().to_rfc3339();
-    if let Err(e) = std::fs::write(heartbeat_path, timestamp) {
+    let timestamp = clock::now().to_rfc3339();
+    let mut content = format!("{}\n{}\n", timestamp, summary);
+    if let Some(freshness) = freshness {
+        content.push_str(&format!("{}\n", freshness));
+    }
+    if let Err(e) = std::fs::write(&heartbeat_path, content) {
         tracing::warn!("Failed to write heartbeat file: {}", e);
     }
 }
+
+/// Timestamp on the first line of the heartbeat file, if any
+fn read_heartbeat() -> Option<DateTime<Utc>> {
+    let content = std::fs::read_to_string(heartbeat_path()).ok()?;
+    let first_line = content.lines().next()?;
+    DateTime::parse_from_rfc3339(first_line)
+        .ok()
+        .map(|t| t.with_timezone(&Utc))
+}
+
+/// Heartbeat file path; a dry-run instance keeps its own next to the scratch database
+pub fn heartbeat_path() -> std::path::PathBuf {
+    match Config::from_env() {
+        Ok(config) if config.dry_run => {
+            std::path::Path::new(&config.db_path).with_file_name("heartbeat")
+        }
+        _ => std::path::PathBuf::from("data/heartbeat"),
+    }
+}
@@ -0,0 +1,94 @@
+use serde::{Deserialize, Serialize};
+
+/// Severity level of a warning kind, ordered 注意報 < 警報 < 特別警報
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+pub enum Severity {
+    #[serde(rename = "注意報", alias = "advisory")]
+    Advisory,
+    #[serde(rename = "警報", alias = "warning")]
+    Warning,
+    #[serde(rename = "特別警報", alias = "emergency")]
+    Emergency,
+}
+
+impl Severity {
+    /// Japanese suffix used in kind names
+    pub fn label(self) -> &'static str {
+        match self {
+            Severity::Advisory => "注意報",
+            Severity::Warning => "警報",
+            Severity::Emergency => "特別警報",
+        }
+    }
+}
+
+/// Direction of a severity change within one phenomenon
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SeverityChange {
+    Upgraded,
+    Downgraded,
+}
+
+impl SeverityChange {
+    /// Describe the change towards `to`, e.g. "upgraded to 警報"
+    pub fn describe(self, to: Severity) -> String {
+        match self {
+            SeverityChange::Upgraded => format!("upgraded to {}", to.label()),
+            SeverityChange::Downgraded => format!("downgraded to {}", to.label()),
+        }
+    }
+}
+
+/// Split a kind name into its phenomenon and severity
+/// Kinds whose advisory has a different name share the warning's phenomenon
+/// (強風注意報 ↔ 暴風警報, 風雪注意報 ↔ 暴風雪警報). Returns `None` for names
+/// without a recognised severity suffix.
+pub fn classify(warning_kind: &str) -> Option<(&str, Severity)> {
+    let (prefix, severity) = if let Some(p) = warning_kind.strip_suffix("特別警報") {
+        (p, Severity::Emergency)
+    } else if let Some(p) = warning_kind.strip_suffix("警報") {
+        (p, Severity::Warning)
+    } else if let Some(p) = warning_kind.strip_suffix("注意報") {
+        (p, Severity::Advisory)
+    } else {
+        return None;
+    };
+
+    let phenomenon = match prefix {
+        "強風" => "暴風",
+        "風雪" => "暴風雪",
+        other => other,
+    };
+    Some((phenomenon, severity))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_kinds_by_suffix() {
+        let cases = [
+            ("大雨注意報", Some(("大雨", Severity::Advisory))),
+            ("大雨警報", Some(("大雨", Severity::Warning))),
+            ("大雨特別警報", Some(("大雨", Severity::Emergency))),
+            ("強風注意報", Some(("暴風", Severity::Advisory))),
+            ("暴風警報", Some(("暴風", Severity::Warning))),
+            ("風雪注意報", Some(("暴風雪", Severity::Advisory))),
+            ("暴風雪警報", Some(("暴風雪", Severity::Warning))),
+            ("暴風雪特別警報", Some(("暴風雪", Severity::Emergency))),
+            ("", None),
+            ("解除", None),
+        ];
+        for (kind, expected) in cases {
+            assert_eq!(classify(kind), expected, "{}", kind);
+        }
+    }
+
+    #[test]
+    fn orders_severities() {
+        assert!(Severity::Advisory < Severity::Warning);
+        assert!(Severity::Warning < Severity::Emergency);
+    }
+}
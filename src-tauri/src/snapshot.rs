@@ -0,0 +1,63 @@
+use crate::clock;
+use crate::config::{Config, MonitorConfig};
+use crate::database::Database;
+use crate::error::Result;
+use crate::notification::{CitySnapshot, EmailNotifier, SnapshotEntry, StartupSnapshot};
+use chrono::{DateTime, Utc};
+
+/// Send every warning in effect for the monitored cities to all recipient groups
+/// Meant to run right after the first successful cycle, when `city_report` has been
+/// reconciled against the latest reports. Entries that came into effect after
+/// `last_seen` (the previous run's last heartbeat) are marked new; without a previous
+/// heartbeat everything is new. Kinds excluded by a city's rules are left out.
+pub async fn send_startup_snapshot(last_seen: Option<DateTime<Utc>>) -> Result<()> {
+    let config = Config::from_env()?;
+    let monitor_config = MonitorConfig::load(MonitorConfig::default_path())?;
+    let db = Database::new().await?;
+
+    let mut cities = Vec::new();
+    for region in &monitor_config.monitored_regions {
+        for city in region.city_names() {
+            let rules = region.notification_rules(city);
+            let mut entries = Vec::new();
+
+            for record in db.get_active_city_reports(&region.lmo, city).await? {
+                if !rules.allows_kind(&record.warning_kind) {
+                    continue;
+                }
+                let active_since = db.get_active_since(&record).await?;
+                entries.push(SnapshotEntry {
+                    is_new: match (active_since, last_seen) {
+                        (Some(since), Some(last)) => since > last,
+                        _ => true,
+                    },
+                    warning_kind: record.warning_kind,
+                    status: record.status,
+                    active_since,
+                });
+            }
+
+            cities.push(CitySnapshot {
+                lmo: region.lmo.clone(),
+                city: city.to_string(),
+                entries,
+            });
+        }
+    }
+
+    let snapshot = StartupSnapshot {
+        taken_at: clock::now(),
+        last_seen,
+        cities,
+    };
+
+    let groups = monitor_config.recipient_groups(&config);
+    let notifier = EmailNotifier::new(config);
+    for group in groups.iter().filter(|g| !g.emergency_only) {
+        if let Err(e) = notifier.send_startup_snapshot(&snapshot, group).await {
+            tracing::warn!("Failed to send startup snapshot to {}: {}", group.name, e);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,230 @@
+use crate::config::{MonitoredRegion, SourceKind};
+use crate::database::{self, Database};
+use crate::error::{Result, WeatherCheckerError};
+use crate::jma_feed::{BosaiWarningReport, JMAFeed, WarningData};
+use chrono::{DateTime, Utc};
+use std::collections::BTreeSet;
+
+/// Latest report for a region: (warnings, report identifier, report datetime)
+/// For the XML source the identifier is the VPWW54 filename; JSON reports use
+/// `bosai:{office}:{datetime}` (`database::JSON_REPORT_PREFIX`), which names no file.
+pub type LatestReport = (Vec<WarningData>, String, DateTime<Utc>);
+
+/// A provider of the current warning state for a monitored region
+pub trait WarningSource {
+    /// Short name used in logs
+    fn name(&self) -> &'static str;
+
+    /// Fetch the latest report for the region
+    /// `None` means the source has no report for the region's LMO at all.
+    async fn fetch_latest(
+        &self,
+        region: &MonitoredRegion,
+        db: &Database,
+    ) -> Result<Option<LatestReport>>;
+}
+
+/// VPWW54 XML via the extra.xml Atom feed
+pub struct XmlFeedSource<'a> {
+    feed: &'a JMAFeed,
+}
+
+impl<'a> XmlFeedSource<'a> {
+    pub fn new(feed: &'a JMAFeed) -> Self {
+        Self { feed }
+    }
+}
+
+impl WarningSource for XmlFeedSource<'_> {
+    fn name(&self) -> &'static str {
+        "xml"
+    }
+
+    async fn fetch_latest(
+        &self,
+        region: &MonitoredRegion,
+        db: &Database,
+    ) -> Result<Option<LatestReport>> {
+        self.feed.get_latest_vpww54_for_lmo(&region.lmo, db).await
+    }
+}
+
+/// bosai warning JSON (`bosai/warning/data/warning/<office>.json`)
+pub struct BosaiJsonSource<'a> {
+    feed: &'a JMAFeed,
+}
+
+impl<'a> BosaiJsonSource<'a> {
+    pub fn new(feed: &'a JMAFeed) -> Self {
+        Self { feed }
+    }
+}
+
+impl WarningSource for BosaiJsonSource<'_> {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    async fn fetch_latest(
+        &self,
+        region: &MonitoredRegion,
+        _db: &Database,
+    ) -> Result<Option<LatestReport>> {
+        let office_code = region.office_code.as_deref().ok_or_else(|| {
+            WeatherCheckerError::Config(format!("office_code not set for {}", region.lmo))
+        })?;
+
+        let report = self.feed.fetch_bosai_warning(office_code).await?;
+        let report_datetime = report.report_datetime.with_timezone(&Utc);
+        let report_id = format!(
+            "{}{}:{}",
+            database::JSON_REPORT_PREFIX,
+            office_code,
+            report_datetime.format("%Y%m%d%H%M%S")
+        );
+
+        Ok(Some((
+            bosai_to_warnings(&report, region),
+            report_id,
+            report_datetime,
+        )))
+    }
+}
+
+/// Fetch the latest report for a region from the given source kind
+pub async fn fetch_from(
+    kind: SourceKind,
+    feed: &JMAFeed,
+    region: &MonitoredRegion,
+    db: &Database,
+) -> Result<Option<LatestReport>> {
+    match kind {
+        SourceKind::Xml => fetch_logged(&XmlFeedSource::new(feed), region, db).await,
+        SourceKind::Json => fetch_logged(&BosaiJsonSource::new(feed), region, db).await,
+    }
+}
+
+async fn fetch_logged<S: WarningSource>(
+    source: &S,
+    region: &MonitoredRegion,
+    db: &Database,
+) -> Result<Option<LatestReport>> {
+    tracing::debug!("Fetching {} from {} source", region.lmo, source.name());
+    source.fetch_latest(region, db).await
+}
+
+/// Compare two reports for a region and log every city whose active warnings disagree
+pub fn log_discrepancies(region: &MonitoredRegion, primary: &LatestReport, other: &LatestReport) {
+    for city in region.city_names() {
+        let a = active_kinds(&primary.0, city);
+        let b = active_kinds(&other.0, city);
+        if a != b {
+            tracing::warn!(
+                "Source mismatch for {} - {}: {} says {:?}, {} says {:?}",
+                region.lmo,
+                city,
+                primary.1,
+                a,
+                other.1,
+                b
+            );
+        }
+    }
+}
+
+/// Kinds currently in effect for a city
+fn active_kinds<'a>(warnings: &'a [WarningData], city: &str) -> BTreeSet<&'a str> {
+    warnings
+        .iter()
+        .filter(|w| {
+            w.city == city && !w.warning_kind.is_empty() && database::is_active_status(&w.status)
+        })
+        .map(|w| w.warning_kind.as_str())
+        .collect()
+}
+
+/// Convert a bosai report into per-city warnings for the region's configured cities
+fn bosai_to_warnings(report: &BosaiWarningReport, region: &MonitoredRegion) -> Vec<WarningData> {
+    let mut result = Vec::new();
+
+    for city in &region.cities {
+        let Some(area_code) = city.area_code() else {
+            tracing::warn!(
+                "No area code for {} - {}, skipping in bosai JSON",
+                region.lmo,
+                city.name
+            );
+            continue;
+        };
+
+        let Some(area) = report
+            .area_types
+            .iter()
+            .flat_map(|t| t.areas.iter())
+            .find(|a| a.code == area_code)
+        else {
+            continue;
+        };
+
+        for warning in &area.warnings {
+            match warning.code.as_deref() {
+                Some(code) => match warning_name(code) {
+                    Some(name) => result.push(WarningData {
+                        city: city.name.clone(),
+                        warning_kind: name.to_string(),
+                        status: warning.status.clone(),
+                    }),
+                    None => tracing::warn!("Unknown bosai warning code: {}", code),
+                },
+                None if warning.status == "発表警報・注意報はなし" => {
+                    result.push(WarningData {
+                        city: city.name.clone(),
+                        warning_kind: String::new(),
+                        status: warning.status.clone(),
+                    });
+                }
+                None => {}
+            }
+        }
+    }
+
+    result
+}
+
+/// Map a bosai warning code to the kind name used in VPWW54
+fn warning_name(code: &str) -> Option<&'static str> {
+    let name = match code {
+        "02" => "暴風雪警報",
+        "03" => "大雨警報",
+        "04" => "洪水警報",
+        "05" => "暴風警報",
+        "06" => "大雪警報",
+        "07" => "波浪警報",
+        "08" => "高潮警報",
+        "10" => "大雨注意報",
+        "12" => "大雪注意報",
+        "13" => "風雪注意報",
+        "14" => "雷注意報",
+        "15" => "強風注意報",
+        "16" => "波浪注意報",
+        "17" => "融雪注意報",
+        "18" => "洪水注意報",
+        "19" => "高潮注意報",
+        "20" => "濃霧注意報",
+        "21" => "乾燥注意報",
+        "22" => "なだれ注意報",
+        "23" => "低温注意報",
+        "24" => "霜注意報",
+        "25" => "着氷注意報",
+        "26" => "着雪注意報",
+        "27" => "その他の注意報",
+        "32" => "暴風雪特別警報",
+        "33" => "大雨特別警報",
+        "35" => "暴風特別警報",
+        "36" => "大雪特別警報",
+        "37" => "波浪特別警報",
+        "38" => "高潮特別警報",
+        _ => return None,
+    };
+    Some(name)
+}
@@ -1,77 +1,331 @@
-use crate::config::{Config, MonitorConfig};
-use crate::database::{CityReport, Database};
-use crate::error::Result;
-use crate::jma_feed::JMAFeed;
-use crate::notification::EmailNotifier;
+use crate::config::{
+    AckConfig, Config, MonitorConfig, MonitoredRegion, NotificationRules, RecipientGroup,
+    SourceKind,
+};
+use crate::database::{
+    is_active_status, is_vpww54_file, CityReport, CityReportChange, Database, NewOutboxMessage,
+};
+use crate::dispatcher::Dispatcher;
+use crate::error::{Result, WeatherCheckerError};
+use crate::jma_feed::{self, JMAFeed, WarningData};
+use crate::notification::{CityNotification, EmailNotifier, WarningChange, CITY_MESSAGE_TYPE};
+use crate::severity::{self, SeverityChange};
+use crate::warning_source::{self, LatestReport};
 
 use chrono::{DateTime, Utc};
+use futures::stream::{self, StreamExt};
+use std::collections::{HashMap, HashSet};
+use tokio::sync::Mutex;
+
+/// NOTE line for a warning closed because the latest report no longer mentions it
+const IMPLICIT_CANCEL_NOTE: &str = "最新の報告に記載がないため解除扱い";
+
+/// Outcome of one check cycle across all monitored regions
+#[derive(Debug, Default)]
+pub struct CycleResult {
+    /// LMOs whose latest report was fetched and processed
+    pub succeeded: Vec<String>,
+    /// LMOs that failed, with the error message
+    pub failed: Vec<(String, String)>,
+    /// LMOs with no report available in this cycle
+    pub skipped: Vec<String>,
+}
+
+impl CycleResult {
+    /// True when every region that was attempted failed
+    pub fn is_total_failure(&self) -> bool {
+        !self.failed.is_empty() && self.succeeded.is_empty() && self.skipped.is_empty()
+    }
+
+    /// One-line summary for logs and the heartbeat file
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "succeeded={} failed={} skipped={}",
+            self.succeeded.len(),
+            self.failed.len(),
+            self.skipped.len()
+        );
+        for (lmo, error) in &self.failed {
+            summary.push_str(&format!("; {}: {}", lmo, error));
+        }
+        summary
+    }
+}
+
+/// Whether a region's check found a report to process
+enum RegionOutcome {
+    Checked,
+    NoReport,
+}
+
+/// Serializes polled checks and pushed feed processing so the same change is never notified twice
+static RUN_LOCK: Mutex<()> = Mutex::const_new(());
 
 pub struct WeatherChecker {
     db: Database,
     jma_feed: JMAFeed,
     notifier: EmailNotifier,
     monitor_config: MonitorConfig,
+    recipient_groups: Vec<RecipientGroup>,
+    ack: Option<AckConfig>,
+    check_concurrency: usize,
+    city_missing_reports: u64,
 }
 
 impl WeatherChecker {
     pub async fn new() -> Result<Self> {
         let config = Config::from_env()?;
-        let db = Database::new().await?;
-        let jma_feed = JMAFeed::new(config.clone());
-        let notifier = EmailNotifier::new(config.clone());
 
         // Load monitor configuration from YAML file
         let config_path = MonitorConfig::default_path();
         let monitor_config = MonitorConfig::load(&config_path)?;
 
+        Self::with_config(config, monitor_config).await
+    }
+
+    /// Build a checker from already loaded configuration
+    pub async fn with_config(config: Config, monitor_config: MonitorConfig) -> Result<Self> {
+        let db = Database::connect(&config.db_path).await?;
+        let jma_feed = JMAFeed::new(config.clone())?;
+        let notifier = EmailNotifier::new(config.clone());
+
         Ok(Self {
             db,
             jma_feed,
             notifier,
+            recipient_groups: monitor_config.recipient_groups(&config),
+            ack: config.ack.clone(),
             monitor_config,
+            check_concurrency: config.check_concurrency.max(1),
+            city_missing_reports: config.city_missing_reports,
         })
     }
 
-    pub async fn run_check(&self) -> Result<()> {
+    pub async fn run_check(&self) -> Result<CycleResult> {
+        let _guard = RUN_LOCK.lock().await;
         tracing::info!("Starting weather check...");
 
-        // Iterate through all monitored regions from config file
+        // Regions sharing an LMO are checked sequentially so city_report writes for the
+        // same LMO and city stay ordered; distinct LMOs run concurrently up to the limit
+        // Groups hold indices rather than references, which keeps the future provably Send
+        let regions = &self.monitor_config.monitored_regions;
+        let mut groups: Vec<Vec<usize>> = Vec::new();
+        for (index, region) in regions.iter().enumerate() {
+            match groups.iter_mut().find(|g| regions[g[0]].lmo == region.lmo) {
+                Some(group) => group.push(index),
+                None => groups.push(vec![index]),
+            }
+        }
+
+        let outcomes: Vec<_> = stream::iter(groups)
+            .map(|group| async move {
+                let mut outcomes = Vec::with_capacity(group.len());
+                for index in group {
+                    let region = &self.monitor_config.monitored_regions[index];
+                    outcomes.push((region, self.check_warnings(region).await));
+                }
+                outcomes
+            })
+            .buffer_unordered(self.check_concurrency)
+            .collect()
+            .await;
+
+        // Each region is checked independently so one failure does not skip the rest
+        let mut cycle = CycleResult::default();
+        for (region, outcome) in outcomes.into_iter().flatten() {
+            match outcome {
+                Ok(RegionOutcome::Checked) => cycle.succeeded.push(region.lmo.clone()),
+                Ok(RegionOutcome::NoReport) => cycle.skipped.push(region.lmo.clone()),
+                Err(e) => {
+                    tracing::error!("Weather check failed for {}: {}", region.lmo, e);
+                    cycle.failed.push((region.lmo.clone(), e.to_string()));
+                }
+            }
+        }
+
+        tracing::info!("Weather check completed ({})", cycle.summary());
+
+        self.dispatch_notifications().await;
+        Ok(cycle)
+    }
+
+    /// Deliver notifications queued during this cycle; failures stay queued for retry
+    async fn dispatch_notifications(&self) {
+        if let Err(e) = Dispatcher::new(&self.db, &self.notifier, &self.recipient_groups)
+            .with_ack(self.ack.as_ref())
+            .dispatch_pending()
+            .await
+        {
+            tracing::error!("Notification dispatch failed: {}", e);
+        }
+    }
+
+    /// Process an Atom feed document pushed by a WebSub hub
+    /// Only regions with a VPWW54 entry in the pushed document are checked; a push carries
+    /// just the new entries, so an absent LMO does not trigger the cleanup path. Fails when
+    /// any region fails, after the others were processed and notifications dispatched.
+    pub async fn process_pushed_feed(&self, feed_xml: &[u8]) -> Result<()> {
+        let _guard = RUN_LOCK.lock().await;
+        tracing::info!("Processing pushed feed...");
+
+        // A push carries the hub's copy of extra.xml, so it counts for feed freshness
+        if let Some(updated) = jma_feed::feed_updated(feed_xml) {
+            self.db
+                .record_feed_updated(jma_feed::EXTRA_FEED, updated)
+                .await?;
+        }
+
+        // Regions on the JSON source are not covered by the XML feed push
+        let mut failed = Vec::new();
         for region in &self.monitor_config.monitored_regions {
-            self.check_warnings(region).await?;
+            if region.source != SourceKind::Xml {
+                continue;
+            }
+            let result = match self
+                .jma_feed
+                .latest_vpww54_in_feed(&region.lmo, feed_xml, &self.db)
+                .await
+            {
+                Ok(Some(latest)) => self.apply_vpww54(region, latest).await,
+                Ok(None) => Ok(()),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                tracing::error!("Pushed feed processing failed for {}: {}", region.lmo, e);
+                failed.push(region.lmo.as_str());
+            }
         }
 
-        tracing::info!("Weather check completed");
+        tracing::info!("Pushed feed processed");
+
+        self.dispatch_notifications().await;
+        if !failed.is_empty() {
+            return Err(WeatherCheckerError::Other(format!(
+                "Pushed feed processing failed for {}",
+                failed.join(", ")
+            )));
+        }
         Ok(())
     }
 
-    async fn check_warnings(&self, region: &crate::config::MonitoredRegion) -> Result<()> {
+    /// Apply a recorded report to every XML-source region of its LMO, without dispatching
+    /// Used by replay; returns false when no monitored region covers the LMO.
+    pub async fn apply_recorded_report(&self, lmo: &str, report: LatestReport) -> Result<bool> {
+        let _guard = RUN_LOCK.lock().await;
+
+        let mut applied = false;
+        for region in &self.monitor_config.monitored_regions {
+            if region.lmo != lmo || region.source != SourceKind::Xml {
+                continue;
+            }
+            self.apply_vpww54(region, report.clone()).await?;
+            applied = true;
+        }
+        Ok(applied)
+    }
+
+    async fn check_warnings(&self, region: &MonitoredRegion) -> Result<RegionOutcome> {
         let lmo = &region.lmo;
-        let city_names = region.city_names();
-        tracing::debug!("Checking warnings for {} - {:?}", lmo, city_names);
+        tracing::debug!("Checking warnings for {} - {:?}", lmo, region.city_names());
 
-        // Get latest VPWW54 data for this LMO
-        let warnings_opt = self
-            .jma_feed
-            .get_latest_vpww54_for_lmo(lmo, &self.db)
-            .await?;
+        // Get latest report for this LMO from the primary source, or the fallback if it fails
+        let warnings_opt =
+            match warning_source::fetch_from(region.source, &self.jma_feed, region, &self.db).await
+            {
+                Ok(latest) => latest,
+                Err(e) => {
+                    let Some(fallback) = region.fallback_source else {
+                        return Err(e);
+                    };
+                    tracing::warn!(
+                        "Primary source {:?} failed for {}, using {:?}: {}",
+                        region.source,
+                        lmo,
+                        fallback,
+                        e
+                    );
+                    warning_source::fetch_from(fallback, &self.jma_feed, region, &self.db).await?
+                }
+            };
 
-        let Some((warnings, xml_filename, control_datetime)) = warnings_opt else {
+        if region.cross_check {
+            self.cross_check(region, warnings_opt.as_ref()).await;
+        }
+
+        let Some(latest) = warnings_opt else {
             // No entry in extra.xml for this LMO
             // Delete cancelled warnings and associated XML records
             tracing::info!("No entry in extra.xml for {}, cleaning up old data", lmo);
             self.db.delete_city_reports_by_lmo(lmo).await?;
             self.db.delete_vpww54_by_lmo(lmo).await?;
-            return Ok(());
+            return Ok(RegionOutcome::NoReport);
         };
 
+        self.apply_vpww54(region, latest).await?;
+        Ok(RegionOutcome::Checked)
+    }
+
+    /// Fetch the region from the other source and log where it disagrees with `primary`
+    /// Cross-checking is diagnostic only; failures are logged and never abort the check.
+    async fn cross_check(&self, region: &MonitoredRegion, primary: Option<&LatestReport>) {
+        let other_kind = match region.source {
+            SourceKind::Xml => SourceKind::Json,
+            SourceKind::Json => SourceKind::Xml,
+        };
+
+        match warning_source::fetch_from(other_kind, &self.jma_feed, region, &self.db).await {
+            Ok(Some(other)) => {
+                if let Some(primary) = primary {
+                    warning_source::log_discrepancies(region, primary, &other);
+                }
+            }
+            Ok(None) => tracing::debug!(
+                "No {:?} report for {} to cross-check",
+                other_kind,
+                region.lmo
+            ),
+            Err(e) => tracing::warn!(
+                "Cross-check via {:?} failed for {}: {}",
+                other_kind,
+                region.lmo,
+                e
+            ),
+        }
+    }
+
+    /// Compare a parsed report against stored state for the region's cities
+    async fn apply_vpww54(
+        &self,
+        region: &MonitoredRegion,
+        (warnings, xml_filename, control_datetime): LatestReport,
+    ) -> Result<()> {
+        let lmo = &region.lmo;
+        let city_names = region.city_names();
+
         // Check if there are any warnings at all
         if warnings.is_empty() {
             tracing::debug!("No warnings in XML for {}", lmo);
             return Ok(());
         }
 
+        self.check_city_presence(region, &warnings, &xml_filename).await?;
+
+        // Kinds mentioned per configured city in this report, for reconciliation below
+        let mut mentioned: HashMap<&str, HashSet<&str>> = HashMap::new();
+        for warning in &warnings {
+            if city_names.contains(&warning.city.as_str()) {
+                mentioned
+                    .entry(warning.city.as_str())
+                    .or_default()
+                    .insert(warning.warning_kind.as_str());
+            }
+        }
+
+        // Status changes per city, committed together once the whole report is read
+        let mut changes: HashMap<&str, Vec<PendingChange>> = HashMap::new();
+
         // Process each warning
-        for warning in warnings {
+        for warning in &warnings {
             // Filter for specified cities
             if !city_names.contains(&warning.city.as_str()) {
                 continue;
@@ -86,7 +340,12 @@ impl WeatherChecker {
                     warning.city
                 );
                 self.db
-                    .delete_city_reports_by_city(lmo, &warning.city)
+                    .delete_city_reports_by_city(
+                        lmo,
+                        &warning.city,
+                        &xml_filename,
+                        &control_datetime,
+                    )
                     .await?;
                 continue;
             }
@@ -96,23 +355,145 @@ impl WeatherChecker {
                 continue;
             }
 
-            let jma_url = region.get_city_url(&warning.city);
-            self.process_warning(
-                lmo,
-                &warning.city,
-                &warning.warning_kind,
-                &warning.status,
-                &xml_filename,
-                jma_url,
-                &control_datetime,
-            )
-            .await?;
+            if let Some(change) = self
+                .process_warning(
+                    lmo,
+                    &warning.city,
+                    &warning.warning_kind,
+                    &warning.status,
+                    &xml_filename,
+                    &region.notification_rules(&warning.city),
+                )
+                .await?
+            {
+                changes
+                    .entry(warning.city.as_str())
+                    .or_default()
+                    .push(change);
+            }
+        }
+
+        for (city, kinds) in &mentioned {
+            let rules = region.notification_rules(city);
+            let cancelled = self
+                .reconcile_city(lmo, city, kinds, &xml_filename, &rules)
+                .await?;
+            changes.entry(city).or_default().extend(cancelled);
+        }
+
+        for (city, city_changes) in changes {
+            self.commit_changes(region, city, city_changes, &control_datetime)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Track whether each configured city appears in the report
+    /// A city missing from `city_missing_reports` consecutive reports is most likely
+    /// misspelt in config.yaml or belongs to another LMO; it is notified once, listing the
+    /// area names the report does contain.
+    async fn check_city_presence(
+        &self,
+        region: &MonitoredRegion,
+        warnings: &[WarningData],
+        report: &str,
+    ) -> Result<()> {
+        let mut appeared: Vec<&str> = Vec::new();
+        for warning in warnings {
+            if !appeared.contains(&warning.city.as_str()) {
+                appeared.push(&warning.city);
+            }
+        }
+
+        for city in region.city_names() {
+            let presence = self
+                .db
+                .record_city_presence(&region.lmo, city, report, appeared.contains(&city))
+                .await?;
+            if self.city_missing_reports == 0
+                || presence.missed_reports < self.city_missing_reports as i64
+                || presence.warned_at.is_some()
+            {
+                continue;
+            }
+
+            tracing::warn!(
+                "{} has not appeared in the last {} reports for {}",
+                city,
+                presence.missed_reports,
+                region.lmo
+            );
+            let details = format!(
+                "{} has not appeared in the last {} reports for {} (last seen in: {}). Check config.yaml. Areas in {}: {}",
+                city,
+                presence.missed_reports,
+                region.lmo,
+                presence.last_seen_report.as_deref().unwrap_or("never"),
+                report,
+                appeared.join("、")
+            );
+            if let Err(e) = self
+                .notifier
+                .send_system_notification("city not found", &details)
+                .await
+            {
+                tracing::warn!("Failed to send city not found notification: {}", e);
+                continue;
+            }
+            self.db.mark_city_missing_warned(&region.lmo, city).await?;
         }
 
         Ok(())
     }
 
-    #[allow(clippy::too_many_arguments)]
+    /// Close active records whose kind the report no longer mentions for a city it covers
+    /// This happens after kind reorganisations and some corrections, where the kind simply
+    /// disappears instead of being reported as 解除. The closure is logged to the event
+    /// history and notified as an implicit cancellation.
+    async fn reconcile_city(
+        &self,
+        lmo: &str,
+        city: &str,
+        mentioned_kinds: &HashSet<&str>,
+        xml_filename: &str,
+        rules: &NotificationRules,
+    ) -> Result<Vec<PendingChange>> {
+        let mut cancelled = Vec::new();
+
+        for record in self.db.get_active_city_reports(lmo, city).await? {
+            if mentioned_kinds.contains(record.warning_kind.as_str()) {
+                continue;
+            }
+
+            tracing::info!(
+                "Implicitly cancelling {} - {} ({}): not mentioned in {}",
+                city,
+                record.warning_kind,
+                record.status,
+                xml_filename
+            );
+
+            let report = CityReport {
+                xml_file: xml_filename.to_string(),
+                status: "解除".to_string(),
+                ..record.clone()
+            };
+            cancelled.push(PendingChange {
+                notify: rules.allows(&report.warning_kind, &report.status),
+                previous: Some(record),
+                report,
+                note: Some(IMPLICIT_CANCEL_NOTE),
+            });
+        }
+
+        Ok(cancelled)
+    }
+
+    /// Compare one warning against its stored record
+    /// Returns the status change to commit, if any, with whether the city's notification
+    /// rules let it notify. XML-file-only changes are written directly since they are
+    /// never notified.
     async fn process_warning(
         &self,
         lmo: &str,
@@ -120,9 +501,10 @@ impl WeatherChecker {
         warning_kind: &str,
         new_status: &str,
         xml_filename: &str,
-        jma_url: Option<&str>,
-        control_datetime: &DateTime<Utc>,
-    ) -> Result<()> {
+        rules: &NotificationRules,
+    ) -> Result<Option<PendingChange>> {
+        let notify = rules.allows(warning_kind, new_status);
+
         // Check if we already have a record for this lmo+city+warning combination
         let existing = self.db.get_city_report(lmo, city, warning_kind).await?;
 
@@ -130,7 +512,7 @@ impl WeatherChecker {
             Some(record) => {
                 // Compare status
                 if record.status != new_status {
-                    // Status changed - send notification and update DB
+                    // Status changed - update DB and queue notification
                     tracing::info!(
                         "Warning status changed for {} - {}: {} -> {}",
                         city,
@@ -139,21 +521,23 @@ impl WeatherChecker {
                         new_status
                     );
 
-                    self.notifier
-                        .send_warning_notification(city, warning_kind, new_status, lmo, jma_url, control_datetime)
-                        .await?;
-
                     // Update record with new status and xml_file
-                    self.db
-                        .update_city_report(record.id.unwrap(), xml_filename, new_status)
-                        .await?;
-
-                    // Add to VPWW54xml table if XML file changed
-                    if record.xml_file != xml_filename {
-                        self.db.add_vpww54_xml(lmo, xml_filename).await?;
-                    }
-                } else if record.xml_file != xml_filename {
+                    let report = CityReport {
+                        xml_file: xml_filename.to_string(),
+                        status: new_status.to_string(),
+                        ..record.clone()
+                    };
+                    return Ok(Some(PendingChange {
+                        previous: Some(record),
+                        report,
+                        note: None,
+                        notify,
+                    }));
+                } else if record.xml_file != xml_filename
+                    && is_vpww54_file(&record.xml_file) == is_vpww54_file(xml_filename)
+                {
                     // Status same but XML file changed - update DB without notification
+                    // A switch between the XML and JSON sources keeps the stored report
                     tracing::debug!(
                         "XML file changed for {} - {} (status unchanged: {})",
                         city,
@@ -177,7 +561,7 @@ impl WeatherChecker {
                 }
             }
             None => {
-                // New warning - send notification and create record
+                // New warning - create record and queue notification
                 tracing::info!(
                     "New warning for {} - {}: {}",
                     city,
@@ -185,10 +569,6 @@ impl WeatherChecker {
                     new_status
                 );
 
-                self.notifier
-                    .send_warning_notification(city, warning_kind, new_status, lmo, jma_url, control_datetime)
-                    .await?;
-
                 let report = CityReport {
                     id: None,
                     xml_file: xml_filename.to_string(),
@@ -199,12 +579,379 @@ impl WeatherChecker {
                     created_at: None,
                     is_delete: false,
                 };
+                return Ok(Some(PendingChange {
+                    previous: None,
+                    report,
+                    note: None,
+                    notify,
+                }));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Commit a city's status changes from one report and queue one notification for them
+    /// A kind that comes into effect while another kind of the same phenomenon ends is
+    /// one severity transition (e.g. 大雨注意報 → 大雨警報) and is listed once. The
+    /// notification lists every change the city's rules let through, plus the full set
+    /// of kinds in effect after the report.
+    async fn commit_changes(
+        &self,
+        region: &MonitoredRegion,
+        city: &str,
+        changes: Vec<PendingChange>,
+        control_datetime: &DateTime<Utc>,
+    ) -> Result<()> {
+        let Some(xml_filename) = changes.first().map(|c| c.report.xml_file.clone()) else {
+            return Ok(());
+        };
 
-                self.db.create_city_report(&report).await?;
-                self.db.add_vpww54_xml(lmo, xml_filename).await?;
+        // Kinds in effect after this report: the stored state with the changes applied
+        let mut active: Vec<(String, String)> = self
+            .db
+            .get_active_city_reports(&region.lmo, city)
+            .await?
+            .into_iter()
+            .map(|r| (r.warning_kind, r.status))
+            .collect();
+        for change in &changes {
+            active.retain(|(kind, _)| *kind != change.report.warning_kind);
+            if is_active_status(&change.report.status) {
+                active.push((
+                    change.report.warning_kind.clone(),
+                    change.report.status.clone(),
+                ));
             }
         }
+        active.sort_by(|a, b| {
+            let severity = |kind: &str| severity::classify(kind).map(|(_, s)| s);
+            severity(&b.0)
+                .cmp(&severity(&a.0))
+                .then_with(|| a.0.cmp(&b.0))
+        });
 
-        Ok(())
+        let groups = pair_severity_transitions(changes);
+        let mut items = Vec::new();
+        for group in &groups {
+            // A transition notifies when either side passes the rules, e.g. a 警報 that
+            // ends as it is downgraded still matters to a 警報-and-above city
+            if !group.notify() {
+                tracing::debug!(
+                    "Notification for {} - {} ({}) filtered by rules",
+                    city,
+                    group.change.report.warning_kind,
+                    group.change.report.status
+                );
+                continue;
+            }
+            if let Some(ended) = &group.ended {
+                tracing::info!(
+                    "Severity transition for {}: {} -> {}",
+                    city,
+                    ended.report.warning_kind,
+                    group.change.report.warning_kind
+                );
+            }
+            items.push(group.warning_change());
+        }
+
+        let message = if items.is_empty() {
+            None
+        } else {
+            let notification = CityNotification {
+                lmo: region.lmo.clone(),
+                city: city.to_string(),
+                jma_url: region.get_city_url(city).map(String::from),
+                control_datetime: *control_datetime,
+                changes: items,
+                active,
+            };
+            Some(city_message(&notification, &xml_filename)?)
+        };
+
+        let records: Vec<CityReportChange> = groups
+            .iter()
+            .flat_map(|group| {
+                let notified = group.notify();
+                group.changes().map(move |c| CityReportChange {
+                    previous: c.previous.as_ref(),
+                    report: &c.report,
+                    notified,
+                })
+            })
+            .collect();
+        self.db
+            .commit_city_report_changes(&records, control_datetime, message.as_ref())
+            .await
+    }
+}
+
+/// A city report status change waiting to be committed
+struct PendingChange {
+    previous: Option<CityReport>,
+    report: CityReport,
+    note: Option<&'static str>,
+    /// Whether the city's notification rules let this change notify
+    notify: bool,
+}
+
+impl PendingChange {
+    /// Whether this change puts the kind into effect
+    fn starts(&self) -> bool {
+        is_active_status(&self.report.status)
+            && !self
+                .previous
+                .as_ref()
+                .is_some_and(|p| is_active_status(&p.status))
+    }
+
+    /// Whether this change ends a kind that was in effect
+    fn ends(&self) -> bool {
+        !is_active_status(&self.report.status)
+            && self
+                .previous
+                .as_ref()
+                .is_some_and(|p| is_active_status(&p.status))
+    }
+}
+
+/// Changes committed under one notification
+struct ChangeGroup {
+    change: PendingChange,
+    /// Kind of the same phenomenon that ended as `change` came into effect
+    ended: Option<PendingChange>,
+}
+
+impl ChangeGroup {
+    fn changes(&self) -> impl Iterator<Item = &PendingChange> {
+        std::iter::once(&self.change).chain(self.ended.as_ref())
+    }
+
+    /// Whether any side of the group passes the city's notification rules
+    fn notify(&self) -> bool {
+        self.changes().any(|c| c.notify)
+    }
+
+    /// Describe the group as one entry of a city notification
+    fn warning_change(&self) -> WarningChange {
+        let report = &self.change.report;
+        let previous_kind = self.ended.as_ref().map(|e| e.report.warning_kind.clone());
+        let severity_change = previous_kind.as_deref().and_then(|previous| {
+            let from = severity::classify(previous)?.1;
+            let to = severity::classify(&report.warning_kind)?.1;
+            Some(if to > from {
+                SeverityChange::Upgraded
+            } else {
+                SeverityChange::Downgraded
+            })
+        });
+
+        WarningChange {
+            warning_kind: report.warning_kind.clone(),
+            previous_status: self.change.previous.as_ref().map(|p| p.status.clone()),
+            status: report.status.clone(),
+            note: self.change.note.map(String::from),
+            previous_kind,
+            severity_change,
+        }
+    }
+}
+
+/// Pair each kind coming into effect with an ending kind of the same phenomenon and a
+/// different severity; everything else stays a single change
+/// The result is ordered by severity, highest first, so the most important
+/// notification of a report is queued first.
+fn pair_severity_transitions(changes: Vec<PendingChange>) -> Vec<ChangeGroup> {
+    let (mut ending, others): (Vec<_>, Vec<_>) = changes.into_iter().partition(|c| c.ends());
+
+    let mut groups = Vec::new();
+    for change in others {
+        let partner = change
+            .starts()
+            .then(|| severity::classify(&change.report.warning_kind))
+            .flatten()
+            .and_then(|(phenomenon, severity)| {
+                ending.iter().position(|e| {
+                    severity::classify(&e.report.warning_kind)
+                        .is_some_and(|(p, s)| p == phenomenon && s != severity)
+                })
+            });
+
+        groups.push(ChangeGroup {
+            change,
+            ended: partner.map(|index| ending.remove(index)),
+        });
+    }
+    groups.extend(ending.into_iter().map(|change| ChangeGroup {
+        change,
+        ended: None,
+    }));
+
+    groups.sort_by_key(|g| {
+        std::cmp::Reverse(
+            g.changes()
+                .filter_map(|c| severity::classify(&c.report.warning_kind))
+                .map(|(_, s)| s)
+                .max(),
+        )
+    });
+    groups
+}
+
+/// Build the outbox message for a city's notification from one report
+/// The dedup key covers the report file, so re-processing the same report never
+/// queues the same notification twice.
+fn city_message(notification: &CityNotification, xml_filename: &str) -> Result<NewOutboxMessage> {
+    Ok(NewOutboxMessage {
+        dedup_key: format!(
+            "{}|{}|{}|{}",
+            CITY_MESSAGE_TYPE, notification.lmo, notification.city, xml_filename
+        ),
+        message_type: CITY_MESSAGE_TYPE.to_string(),
+        payload: serde_json::to_string(notification)
+            .map_err(|e| WeatherCheckerError::Other(format!("Failed to encode payload: {}", e)))?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(kind: &str, status: &str) -> CityReport {
+        CityReport {
+            id: None,
+            xml_file: "20250101000000_0_VPWW54_130000.xml".to_string(),
+            lmo: "東京都".to_string(),
+            city: "千代田区".to_string(),
+            warning_kind: kind.to_string(),
+            status: status.to_string(),
+            created_at: None,
+            is_delete: false,
+        }
+    }
+
+    fn change(kind: &str, previous: Option<&str>, status: &str) -> PendingChange {
+        PendingChange {
+            previous: previous.map(|s| report(kind, s)),
+            report: report(kind, status),
+            note: None,
+            notify: true,
+        }
+    }
+
+    /// (kind, previous kind, severity change) of each group, in order
+    fn summarise(groups: &[ChangeGroup]) -> Vec<(String, Option<String>, Option<SeverityChange>)> {
+        groups
+            .iter()
+            .map(|g| {
+                let c = g.warning_change();
+                (c.warning_kind, c.previous_kind, c.severity_change)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pairs_upgrade_and_downgrade_of_one_phenomenon() {
+        let groups = pair_severity_transitions(vec![
+            change("大雨注意報", Some("継続"), "解除"),
+            change("大雨警報", None, "発表"),
+            change("大雪警報", Some("継続"), "解除"),
+            change("大雪注意報", None, "発表"),
+        ]);
+        assert_eq!(
+            summarise(&groups),
+            vec![
+                (
+                    "大雨警報".to_string(),
+                    Some("大雨注意報".to_string()),
+                    Some(SeverityChange::Upgraded)
+                ),
+                (
+                    "大雪注意報".to_string(),
+                    Some("大雪警報".to_string()),
+                    Some(SeverityChange::Downgraded)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn pairs_advisories_named_after_another_phenomenon() {
+        let groups = pair_severity_transitions(vec![
+            change("強風注意報", Some("発表"), "解除"),
+            change("暴風警報", None, "発表"),
+            change("風雪注意報", Some("継続"), "解除"),
+            change("暴風雪警報", None, "発表"),
+        ]);
+        assert_eq!(
+            summarise(&groups),
+            vec![
+                (
+                    "暴風警報".to_string(),
+                    Some("強風注意報".to_string()),
+                    Some(SeverityChange::Upgraded)
+                ),
+                (
+                    "暴風雪警報".to_string(),
+                    Some("風雪注意報".to_string()),
+                    Some(SeverityChange::Upgraded)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn leaves_unrelated_and_continuing_changes_unpaired() {
+        let groups = pair_severity_transitions(vec![
+            // Different phenomenon
+            change("雷注意報", Some("継続"), "解除"),
+            change("大雨警報", None, "発表"),
+            // Already in effect, so it does not start anything
+            change("洪水警報", Some("発表"), "継続"),
+            change("洪水注意報", Some("継続"), "解除"),
+            // Same severity is not a transition
+            change("高潮注意報", Some("継続"), "解除"),
+            change("高潮注意報", None, "発表"),
+        ]);
+        let summary = summarise(&groups);
+        assert_eq!(summary.len(), 6);
+        assert!(summary
+            .iter()
+            .all(|(_, previous, severity)| previous.is_none() && severity.is_none()));
+    }
+
+    #[test]
+    fn orders_groups_by_highest_severity() {
+        let groups = pair_severity_transitions(vec![
+            change("雷注意報", None, "発表"),
+            change("大雨特別警報", None, "発表"),
+            change("洪水警報", None, "発表"),
+        ]);
+        let kinds: Vec<_> = summarise(&groups).into_iter().map(|(k, _, _)| k).collect();
+        assert_eq!(kinds, vec!["大雨特別警報", "洪水警報", "雷注意報"]);
+    }
+
+    #[test]
+    fn pairs_upgrade_with_advisory_closed_by_reconciliation() {
+        // The report announces 大雨警報 and no longer mentions 大雨注意報, which
+        // reconcile_city closes as an implicit cancellation
+        let mut cancelled = change("大雨注意報", Some("継続"), "解除");
+        cancelled.note = Some(IMPLICIT_CANCEL_NOTE);
+
+        let groups = pair_severity_transitions(vec![change("大雨警報", None, "発表"), cancelled]);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(
+            groups[0].ended.as_ref().and_then(|e| e.note),
+            Some(IMPLICIT_CANCEL_NOTE)
+        );
+        assert_eq!(
+            summarise(&groups),
+            vec![(
+                "大雨警報".to_string(),
+                Some("大雨注意報".to_string()),
+                Some(SeverityChange::Upgraded)
+            )]
+        );
     }
 }
@@ -0,0 +1,372 @@
+use crate::clock;
+use crate::config::{Config, WebSubConfig};
+use crate::error::{Result, WeatherCheckerError};
+use crate::jma_feed::JMAFeed;
+use crate::weather_checker::WeatherChecker;
+use axum::body::Bytes;
+use axum::extract::{Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::routing::get;
+use axum::Router;
+use hmac::{Hmac, Mac};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+/// Unix timestamp of the last successfully processed content delivery (0 = never)
+static LAST_PUSH_AT: AtomicI64 = AtomicI64::new(0);
+
+/// Verified deliveries waiting to be processed; further ones are dropped while full
+const DELIVERY_QUEUE_SIZE: usize = 16;
+
+/// State shared by the callback handlers
+struct Subscriber {
+    websub: WebSubConfig,
+    /// Verified deliveries, processed one at a time by `process_deliveries`
+    deliveries: mpsc::Sender<Bytes>,
+}
+
+/// Whether a pushed feed was processed successfully within the fallback window
+/// While this is true the scheduler skips its polling cycle.
+pub fn push_is_fresh(fallback_secs: u64) -> bool {
+    let last = LAST_PUSH_AT.load(Ordering::Relaxed);
+    last > 0 && clock::now().timestamp() - last < fallback_secs as i64
+}
+
+/// Run the WebSub subscriber: HTTP callback endpoint plus periodic (re)subscription
+/// Returns when the cancellation token fires.
+pub async fn run(config: Config, cancel_token: CancellationToken) -> Result<()> {
+    let websub = config
+        .websub
+        .clone()
+        .ok_or_else(|| WeatherCheckerError::Config("WebSub is not enabled".into()))?;
+
+    let callback_path = reqwest::Url::parse(&websub.callback_url)
+        .map_err(|e| {
+            WeatherCheckerError::Config(format!(
+                "Invalid WEBSUB_CALLBACK_URL '{}': {}",
+                websub.callback_url, e
+            ))
+        })?
+        .path()
+        .to_string();
+
+    let checker = WeatherChecker::new().await?;
+    let (deliveries, queue) = mpsc::channel(DELIVERY_QUEUE_SIZE);
+    let worker = tokio::spawn(process_deliveries(checker, queue, cancel_token.clone()));
+
+    let app = Router::new()
+        .route(&callback_path, get(verify_intent).post(receive_content))
+        .with_state(Arc::new(Subscriber {
+            websub: websub.clone(),
+            deliveries,
+        }));
+
+    let listener = tokio::net::TcpListener::bind(&websub.listen_addr).await?;
+    tracing::info!(
+        "WebSub subscriber listening on {}{}",
+        websub.listen_addr,
+        callback_path
+    );
+
+    let server_token = cancel_token.clone();
+    let server = tokio::spawn(async move {
+        axum::serve(listener, app)
+            .with_graceful_shutdown(async move { server_token.cancelled().await })
+            .await
+    });
+
+    let feed = JMAFeed::new(config)?;
+    // Renew well before the lease expires
+    let renew_every = std::time::Duration::from_secs((websub.lease_secs * 9 / 10).max(60));
+
+    loop {
+        if let Err(e) = feed
+            .websub_subscribe(
+                &websub.hub_url,
+                &websub.topic_url,
+                &websub.callback_url,
+                &websub.secret,
+                websub.lease_secs,
+            )
+            .await
+        {
+            tracing::error!("WebSub subscription request failed: {}", e);
+        }
+
+        tokio::select! {
+            _ = cancel_token.cancelled() => break,
+            _ = tokio::time::sleep(renew_every) => {}
+        }
+    }
+
+    if let Err(e) = worker.await {
+        tracing::error!("WebSub delivery worker failed: {}", e);
+    }
+    match server.await {
+        Ok(result) => result?,
+        Err(e) => tracing::error!("WebSub server task failed: {}", e),
+    }
+    tracing::info!("WebSub subscriber stopped");
+    Ok(())
+}
+
+/// Process queued deliveries in order with one checker until cancelled
+/// Only a delivery processed without error counts as a push, so polling resumes after
+/// the fallback window when pushed feeds keep failing.
+async fn process_deliveries(
+    checker: WeatherChecker,
+    mut queue: mpsc::Receiver<Bytes>,
+    cancel_token: CancellationToken,
+) {
+    loop {
+        let body = tokio::select! {
+            _ = cancel_token.cancelled() => break,
+            body = queue.recv() => match body {
+                Some(body) => body,
+                None => break,
+            },
+        };
+
+        match checker.process_pushed_feed(&body).await {
+            Ok(()) => LAST_PUSH_AT.store(clock::now().timestamp(), Ordering::Relaxed),
+            Err(e) => tracing::error!("Failed to process pushed feed: {}", e),
+        }
+    }
+}
+
+/// Hub verification of intent: echo `hub.challenge` for our topic
+async fn verify_intent(
+    State(subscriber): State<Arc<Subscriber>>,
+    Query(params): Query<HashMap<String, String>>,
+) -> (StatusCode, String) {
+    let websub = &subscriber.websub;
+    let mode = params.get("hub.mode").map(String::as_str).unwrap_or("");
+    let topic = params.get("hub.topic").map(String::as_str).unwrap_or("");
+
+    match mode {
+        "subscribe" | "unsubscribe" if topic == websub.topic_url => {
+            let Some(challenge) = params.get("hub.challenge") else {
+                return (StatusCode::BAD_REQUEST, String::new());
+            };
+            tracing::info!(
+                "WebSub {} verified for {} (lease: {})",
+                mode,
+                topic,
+                params
+                    .get("hub.lease_seconds")
+                    .map(String::as_str)
+                    .unwrap_or("-")
+            );
+            (StatusCode::OK, challenge.clone())
+        }
+        "denied" => {
+            tracing::warn!(
+                "WebSub subscription denied for {}: {}",
+                topic,
+                params
+                    .get("hub.reason")
+                    .map(String::as_str)
+                    .unwrap_or("no reason given")
+            );
+            (StatusCode::OK, String::new())
+        }
+        _ => {
+            tracing::warn!(
+                "Rejecting WebSub verification: mode={}, topic={}",
+                mode,
+                topic
+            );
+            (StatusCode::NOT_FOUND, String::new())
+        }
+    }
+}
+
+/// Content distribution: verify the HMAC signature and queue the pushed feed
+/// Per the WebSub spec, unsigned or badly signed content is acknowledged with 2xx but ignored.
+async fn receive_content(
+    State(subscriber): State<Arc<Subscriber>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> StatusCode {
+    let signature = headers.get("X-Hub-Signature").and_then(|v| v.to_str().ok());
+
+    if !signature.is_some_and(|sig| verify_signature(&subscriber.websub.secret, sig, &body)) {
+        tracing::warn!("Ignoring WebSub delivery with missing or invalid signature");
+        return StatusCode::ACCEPTED;
+    }
+
+    tracing::info!("Received WebSub delivery ({} bytes)", body.len());
+
+    // Acknowledge immediately; hubs expect a prompt response. A dropped delivery is
+    // picked up by polling once pushes stop counting as fresh.
+    if let Err(e) = subscriber.deliveries.try_send(body) {
+        tracing::warn!("Dropping WebSub delivery: {}", e);
+    }
+
+    StatusCode::ACCEPTED
+}
+
+/// Verify an `X-Hub-Signature` header of the form `<algo>=<hex digest>`
+fn verify_signature(secret: &str, header: &str, body: &[u8]) -> bool {
+    let Some((algo, digest)) = header.split_once('=') else {
+        return false;
+    };
+    let Ok(expected) = hex::decode(digest) else {
+        return false;
+    };
+
+    match algo {
+        "sha1" => verify_hmac::<Hmac<sha1::Sha1>>(secret, body, &expected),
+        "sha256" => verify_hmac::<Hmac<sha2::Sha256>>(secret, body, &expected),
+        "sha384" => verify_hmac::<Hmac<sha2::Sha384>>(secret, body, &expected),
+        "sha512" => verify_hmac::<Hmac<sha2::Sha512>>(secret, body, &expected),
+        _ => false,
+    }
+}
+
+fn verify_hmac<M: Mac + hmac::digest::KeyInit>(secret: &str, body: &[u8], expected: &[u8]) -> bool {
+    let Ok(mut mac) = <M as hmac::digest::KeyInit>::new_from_slice(secret.as_bytes()) else {
+        return false;
+    };
+    mac.update(body);
+    mac.verify_slice(expected).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOPIC: &str = "https://www.data.jma.go.jp/developer/xml/feed/extra.xml";
+    const SECRET: &str = "test-secret";
+
+    fn subscriber() -> (Arc<Subscriber>, mpsc::Receiver<Bytes>) {
+        let (deliveries, queue) = mpsc::channel(1);
+        let websub = WebSubConfig {
+            listen_addr: "127.0.0.1:0".to_string(),
+            callback_url: "http://127.0.0.1/websub".to_string(),
+            hub_url: "http://127.0.0.1/hub".to_string(),
+            topic_url: TOPIC.to_string(),
+            secret: SECRET.to_string(),
+            lease_secs: 86400,
+            fallback_secs: 1800,
+        };
+        (Arc::new(Subscriber { websub, deliveries }), queue)
+    }
+
+    fn query(pairs: &[(&str, &str)]) -> Query<HashMap<String, String>> {
+        Query(
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        )
+    }
+
+    fn sign(algo: &str, body: &[u8]) -> HeaderMap {
+        let digest = match algo {
+            "sha1" => {
+                let mut mac = Hmac::<sha1::Sha1>::new_from_slice(SECRET.as_bytes()).unwrap();
+                mac.update(body);
+                hex::encode(mac.finalize().into_bytes())
+            }
+            _ => {
+                let mut mac = Hmac::<sha2::Sha256>::new_from_slice(SECRET.as_bytes()).unwrap();
+                mac.update(body);
+                hex::encode(mac.finalize().into_bytes())
+            }
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "X-Hub-Signature",
+            format!("{}={}", algo, digest).parse().unwrap(),
+        );
+        headers
+    }
+
+    #[tokio::test]
+    async fn echoes_challenge_for_our_topic() {
+        let (status, body) = verify_intent(
+            State(subscriber().0),
+            query(&[
+                ("hub.mode", "subscribe"),
+                ("hub.topic", TOPIC),
+                ("hub.challenge", "abc123"),
+                ("hub.lease_seconds", "86400"),
+            ]),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "abc123");
+    }
+
+    #[tokio::test]
+    async fn rejects_verification_for_unknown_topic() {
+        let (status, body) = verify_intent(
+            State(subscriber().0),
+            query(&[
+                ("hub.mode", "subscribe"),
+                ("hub.topic", "https://example.com/other.xml"),
+                ("hub.challenge", "abc123"),
+            ]),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert!(body.is_empty());
+    }
+
+    #[test]
+    fn verifies_signatures() {
+        let body = b"<feed>payload</feed>";
+        for algo in ["sha1", "sha256"] {
+            let header = sign(algo, body);
+            let header = header["X-Hub-Signature"].to_str().unwrap();
+            assert!(verify_signature(SECRET, header, body), "{}", algo);
+            assert!(!verify_signature("other-secret", header, body), "{}", algo);
+            assert!(!verify_signature(SECRET, header, b"<feed>tampered</feed>"));
+        }
+        assert!(!verify_signature(SECRET, "sha256=not-hex", body));
+        assert!(!verify_signature(SECRET, "md5=00", body));
+        assert!(!verify_signature(SECRET, "no-separator", body));
+    }
+
+    /// Bad and valid deliveries are all acknowledged, but only a valid one is queued
+    /// Receiving alone never counts as a push; that waits until the feed is processed.
+    #[tokio::test]
+    async fn queues_only_validly_signed_deliveries() {
+        let (subscriber, mut queue) = subscriber();
+        let body = Bytes::from_static(b"<feed>payload</feed>");
+
+        let status =
+            receive_content(State(subscriber.clone()), HeaderMap::new(), body.clone()).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        assert!(queue.try_recv().is_err());
+
+        let mut bad = sign("sha256", &body);
+        bad.insert("X-Hub-Signature", "sha256=00ff".parse().unwrap());
+        let status = receive_content(State(subscriber.clone()), bad, body.clone()).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        assert!(queue.try_recv().is_err());
+
+        let status = receive_content(
+            State(subscriber.clone()),
+            sign("sha256", &body),
+            body.clone(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        assert_eq!(queue.try_recv().unwrap(), body);
+        assert!(!push_is_fresh(1800));
+
+        // A full queue still acknowledges without blocking
+        for _ in 0..2 {
+            let status =
+                receive_content(State(subscriber.clone()), sign("sha1", &body), body.clone()).await;
+            assert_eq!(status, StatusCode::ACCEPTED);
+        }
+        assert_eq!(queue.try_recv().unwrap(), body);
+        assert!(queue.try_recv().is_err());
+    }
+}